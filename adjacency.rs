@@ -0,0 +1,273 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::names::NameMatching;
+use crate::{Dungeon, Errors, Room};
+
+// the exits of every room by number instead of by name, kept up to date by every change of the
+// dungeon so path queries don't build a graph of their own
+//
+// a number is free again once its room is removed and goes to the next room that is added. With the
+// route table on, every change also works out the first step of the shortest way between every
+// two rooms, which makes `next_hop` a lookup. The table takes rooms² numbers and a search from
+// every room on each change, it's meant for small maps that are asked a lot and changed rarely.
+// Hidden exits are never part of the table.
+//
+// it also finds rooms by the names players type, the name and aliases of every room go in as
+// keys made by the dungeon's `NameMatching`.
+
+#[derive(Clone, Default)]
+pub(crate) struct Adjacency {
+    ids: HashMap<String, usize>,
+    //the room name of every number, empty for free numbers
+    names: Vec<String>,
+    //the rooms behind the exits of every room, and whether the exit is hidden
+    exits: Vec<Vec<(usize, bool)>>,
+    free: Vec<usize>,
+    //`next_hop[from * names.len() + to]`, while the table is on
+    next_hop: Option<Vec<Option<usize>>>,
+    matching: NameMatching,
+    keys: HashMap<String, usize>,
+    //the keys of every number, the one for the room name first
+    room_keys: Vec<Vec<String>>,
+}
+
+impl Adjacency {
+    fn id(&mut self, room_name: &str) -> usize {
+        if let Some(id) = self.ids.get(room_name) {
+            return *id;
+        }
+        let id = match self.free.pop() {
+            Some(id) => id,
+            None => {
+                self.names.push(String::new());
+                self.exits.push(Vec::new());
+                self.room_keys.push(Vec::new());
+                self.names.len() - 1
+            }
+        };
+        self.names[id] = room_name.to_string();
+        self.ids.insert(room_name.to_string(), id);
+        id
+    }
+
+    // brings one room up to date, `None` when it doesn't exist (any more)
+    pub(crate) fn update(&mut self, room_name: &str, room: Option<&Room>) {
+        match room {
+            Some(room) => {
+                let id = self.id(room_name);
+                self.exits[id] = room.next_to.iter().map(|exit| (self.id(&exit.to), exit.hidden)).collect();
+                self.set_keys(id, std::iter::once(&room.name).chain(&room.aliases));
+            }
+            None => {
+                if let Some(id) = self.ids.remove(room_name) {
+                    self.names[id].clear();
+                    self.exits[id].clear();
+                    self.set_keys(id, []);
+                    self.free.push(id);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn rename(&mut self, room_name: &str, new_name: &str) {
+        if let Some(id) = self.ids.remove(room_name) {
+            self.names[id] = new_name.to_string();
+            self.ids.insert(new_name.to_string(), id);
+            let key = self.matching.key(new_name);
+            if self.keys.get(&self.room_keys[id][0]) == Some(&id) {
+                self.keys.remove(&self.room_keys[id][0]);
+            }
+            self.keys.insert(key.clone(), id);
+            self.room_keys[id][0] = key;
+        }
+    }
+
+    fn set_keys<'a>(&mut self, id: usize, names: impl IntoIterator<Item = &'a String>) {
+        for key in self.room_keys[id].drain(..) {
+            //a key can already belong to a room that was put back before this one was removed
+            if self.keys.get(&key) == Some(&id) {
+                self.keys.remove(&key);
+            }
+        }
+        let keys: Vec<String> = names.into_iter().map(|name| self.matching.key(name)).collect();
+        for key in &keys {
+            self.keys.insert(key.clone(), id);
+        }
+        self.room_keys[id] = keys;
+    }
+
+    pub(crate) fn matching(&self) -> NameMatching {
+        self.matching
+    }
+
+    pub(crate) fn set_matching<'a>(&mut self, matching: NameMatching, rooms: impl IntoIterator<Item = &'a Room>) {
+        self.matching = matching;
+        self.keys.clear();
+        for room in rooms {
+            let id = self.ids[&room.name];
+            self.set_keys(id, std::iter::once(&room.name).chain(&room.aliases));
+        }
+    }
+
+    // the room name a name or alias matches
+    pub(crate) fn find(&self, name: &str) -> Option<&str> {
+        let id = match self.matching {
+            NameMatching::Exact => self.keys.get(name),
+            matching => self.keys.get(&matching.key(name)),
+        };
+        id.map(|id| self.names[*id].as_str())
+    }
+
+    // the table is worked out again after every change while it's on
+    pub(crate) fn changed(&mut self) {
+        if self.next_hop.is_some() {
+            self.next_hop = Some(self.routes());
+        }
+    }
+
+    fn routes(&self) -> Vec<Option<usize>> {
+        let count = self.names.len();
+        let mut table = vec![None; count * count];
+        for from in 0..count {
+            //the first step towards every room found so far
+            let first = &mut table[from * count..(from + 1) * count];
+            let mut queue = VecDeque::from([from]);
+            while let Some(room) = queue.pop_front() {
+                for &(next, hidden) in &self.exits[room] {
+                    if hidden || next == from || first[next].is_some() {
+                        continue;
+                    }
+                    first[next] = if room == from { Some(next) } else { first[room] };
+                    queue.push_back(next);
+                }
+            }
+        }
+        table
+    }
+
+    // the shortest way as room numbers, both ends included
+    fn path(&self, from: usize, to: usize, use_hidden: bool) -> Option<Vec<usize>> {
+        if let (Some(table), false) = (&self.next_hop, use_hidden) {
+            let mut path = vec![from];
+            while *path.last().unwrap() != to {
+                path.push(table[path.last().unwrap() * self.names.len() + to]?);
+            }
+            return Some(path);
+        }
+
+        let mut previous: Vec<Option<usize>> = vec![None; self.names.len()];
+        let mut queue = VecDeque::from([from]);
+        while let Some(room) = queue.pop_front() {
+            if room == to {
+                let mut path = vec![to];
+                while *path.last().unwrap() != from {
+                    path.push(previous[*path.last().unwrap()].unwrap());
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for &(next, hidden) in &self.exits[room] {
+                if (use_hidden || !hidden) && next != from && previous[next].is_none() {
+                    previous[next] = Some(room);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Dungeon {
+    // keeps the first step of the shortest way between every two rooms, see `next_hop`
+    pub fn start_route_table(&mut self) {
+        self.adjacency.next_hop = Some(self.adjacency.routes());
+    }
+
+    pub fn stop_route_table(&mut self) {
+        self.adjacency.next_hop = None;
+    }
+
+    // the room to go to from `start_room_name` on the shortest way to `end_room_name` without
+    // hidden exits, `None` when there is no way or the two are the same room. A single lookup
+    // while the route table is on
+    pub fn next_hop(&self, start_room_name: &str, end_room_name: &str) -> Result<Option<&Room>, Errors> {
+        let (from, to) = (self.room_id(start_room_name)?, self.room_id(end_room_name)?);
+        let next = match &self.adjacency.next_hop {
+            Some(table) => table[from * self.adjacency.names.len() + to],
+            None => self.adjacency.path(from, to, false).and_then(|path| path.get(1).copied()),
+        };
+        Ok(next.map(|id| &self.rooms[&self.adjacency.names[id]]))
+    }
+
+    fn room_id(&self, room_name: &str) -> Result<usize, Errors> {
+        let room_name = self.adjacency.find(room_name).unwrap_or(room_name);
+        self.adjacency.ids.get(room_name).copied().ok_or_else(|| Errors::UnknownRoom(room_name.to_string()))
+    }
+
+    // the shortest way between two rooms, by name
+    pub(crate) fn room_path(
+        &self,
+        start_room_name: &str,
+        end_room_name: &str,
+        use_hidden: bool,
+    ) -> Result<Option<Vec<&str>>, Errors> {
+        let (from, to) = (self.room_id(start_room_name)?, self.room_id(end_room_name)?);
+        let path = self.adjacency.path(from, to, use_hidden);
+        Ok(path.map(|path| path.into_iter().map(|id| self.adjacency.names[id].as_str()).collect()))
+    }
+
+    // brings the adjacency of a room up to date after a change
+    pub(crate) fn sync_room(&mut self, room_name: &str) {
+        self.adjacency.update(room_name, self.rooms.get(room_name));
+        self.adjacency.changed();
+    }
+}
+
+#[cfg(test)]
+fn test_dungeon() -> Dungeon {
+    use crate::Direction;
+
+    let mut dungeon = Dungeon::new();
+    for room in ["Entrance", "Hallway", "Library", "Cellar", "Vault"] {
+        dungeon.add_room(room).unwrap();
+    }
+    dungeon.set_link("Entrance", Direction::North, "Hallway").unwrap();
+    dungeon.set_link("Hallway", Direction::East, "Library").unwrap();
+    dungeon.set_link("Library", Direction::South, "Cellar").unwrap();
+    dungeon.set_hidden_link("Cellar", Direction::East, "Vault").unwrap();
+    dungeon
+}
+
+#[test]
+fn test_next_hops() {
+    use crate::Direction;
+
+    for table in [false, true] {
+        let mut dungeon = test_dungeon();
+        if table {
+            dungeon.start_route_table();
+        }
+        let hop = |d: &Dungeon, from, to| d.next_hop(from, to).unwrap().map(|room| room.name.clone());
+        assert_eq!(hop(&dungeon, "Entrance", "Cellar").as_deref(), Some("Hallway"));
+        assert_eq!(hop(&dungeon, "Cellar", "Entrance").as_deref(), Some("Library"));
+        assert_eq!(hop(&dungeon, "Entrance", "Vault"), None);
+        assert_eq!(hop(&dungeon, "Entrance", "Entrance"), None);
+        assert!(dungeon.next_hop("Entrance", "Attic").is_err());
+
+        //changes are seen right away
+        dungeon.set_link("Entrance", Direction::East, "Cellar").unwrap();
+        assert_eq!(hop(&dungeon, "Entrance", "Cellar").as_deref(), Some("Cellar"));
+        dungeon.rename_room("Cellar", "Crypt").unwrap();
+        assert_eq!(hop(&dungeon, "Library", "Entrance").as_deref(), Some("Crypt"));
+        dungeon.remove_room("Crypt").unwrap();
+        assert_eq!(hop(&dungeon, "Library", "Entrance").as_deref(), Some("Hallway"));
+        dungeon.add_room("Crypt").unwrap();
+        assert!(dungeon.find_path("Crypt", "Entrance").unwrap().is_none());
+
+        dungeon.start_journal();
+        dungeon.set_link("Vault", Direction::North, "Library").unwrap();
+        assert_eq!(hop(&dungeon, "Entrance", "Vault").as_deref(), Some("Hallway"));
+        dungeon.undo();
+        assert_eq!(hop(&dungeon, "Entrance", "Vault"), None);
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::maze::Rng;
+use crate::{Dungeon, Errors, ExitKind, ALL_DIRECTIONS};
+
+// npcs and monsters that move through the dungeon on every tick of a simulation
+// on each tick the agents act one after the other, in the order they were added, and every
+// agent moves at most one room. Agents don't pass doors that are locked in the dungeon.
+// Wandering uses a seeded generator, so the same seed and the same ticks always give the
+// same moves. Hidden doors are secrets for the player to find, agents don't use them either.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Behaviour {
+    Stay,
+    //walks to every room of the route in turn and starts over after the last one
+    Patrol(Vec<String>),
+    Wander,
+    //follows the player along the shortest way through the doors agents can pass
+    Chase,
+    //moves to the neighbour that is furthest from the player
+    Flee,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AgentId(usize);
+
+#[derive(Debug, Clone)]
+pub struct Agent {
+    pub name: String,
+    pub room: String,
+    pub behaviour: Behaviour,
+    //index of the next room of a patrol route
+    waypoint: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentMove {
+    pub agent: AgentId,
+    pub from: String,
+    pub to: String,
+}
+
+pub struct Simulation<'a> {
+    dungeon: &'a Dungeon,
+    agents: Vec<Agent>,
+    tick: u64,
+    rng: Rng,
+}
+
+impl<'a> Simulation<'a> {
+    pub fn new(dungeon: &'a Dungeon, seed: u64) -> Self {
+        Simulation { dungeon, agents: Vec::new(), tick: 0, rng: Rng::new(seed) }
+    }
+
+    // rooms can be given by an alias, agents keep the room's own name
+    pub fn add_agent(&mut self, name: &str, room_name: &str, mut behaviour: Behaviour) -> Result<AgentId, Errors> {
+        let room_name = self.dungeon.get_room(room_name)?.name.clone();
+        if let Behaviour::Patrol(route) = &mut behaviour {
+            for room in route {
+                *room = self.dungeon.get_room(room)?.name.clone();
+            }
+        }
+        self.agents.push(Agent {
+            name: name.to_string(),
+            room: room_name,
+            behaviour,
+            waypoint: 0,
+        });
+        Ok(AgentId(self.agents.len() - 1))
+    }
+
+    pub fn agent(&self, id: AgentId) -> &Agent {
+        &self.agents[id.0]
+    }
+
+    pub fn agents(&self) -> impl Iterator<Item = (AgentId, &Agent)> {
+        self.agents.iter().enumerate().map(|(i, agent)| (AgentId(i), agent))
+    }
+
+    pub fn agents_in<'s>(&'s self, room_name: &'s str) -> impl Iterator<Item = (AgentId, &'s Agent)> {
+        let room_name = self.dungeon.resolve_room_name(room_name).unwrap_or(room_name);
+        self.agents().filter(move |(_, agent)| agent.room == room_name)
+    }
+
+    pub fn current_tick(&self) -> u64 {
+        self.tick
+    }
+
+    // chasing and fleeing agents stay where they are if there is no player
+    pub fn tick(&mut self, player_room_name: Option<&str>) -> Result<Vec<AgentMove>, Errors> {
+        let dungeon = self.dungeon;
+        let player_room_name = match player_room_name {
+            Some(player) => Some(dungeon.get_room(player)?.name.as_str()),
+            None => None,
+        };
+        self.tick += 1;
+
+        let mut moves = Vec::new();
+        for index in 0..self.agents.len() {
+            if let Some(next) = self.next_room(index, player_room_name)? {
+                let agent = &mut self.agents[index];
+                let from = std::mem::replace(&mut agent.room, next.clone());
+                moves.push(AgentMove { agent: AgentId(index), from, to: next });
+            }
+        }
+        Ok(moves)
+    }
+
+    // the rooms next to this one through doors that are neither locked nor hidden
+    fn exits(&self, room_name: &str) -> Vec<String> {
+        let room = match self.dungeon.get_room(room_name) {
+            Ok(room) => room,
+            Err(_) => return Vec::new(),
+        };
+        ALL_DIRECTIONS
+            .iter()
+            .filter(|dir| !self.dungeon.is_locked(room_name, **dir))
+            .filter_map(|dir| room.next_to.get(&ExitKind::Compass(*dir)))
+            .filter(|exit| !exit.hidden)
+            .map(|exit| exit.to.clone())
+            .collect()
+    }
+
+    // the first room on the shortest way from one room to another through `exits`, `None` when
+    // there is no such way and the agent waits
+    fn step_towards(&self, from: &str, to: &str) -> Option<String> {
+        //the first room on the way to every room found so far
+        let mut first_steps: HashMap<String, String> = HashMap::new();
+        let mut queue = VecDeque::new();
+        for next in self.exits(from) {
+            if !first_steps.contains_key(&next) {
+                first_steps.insert(next.clone(), next.clone());
+                queue.push_back(next);
+            }
+        }
+        while let Some(room) = queue.pop_front() {
+            if room == to {
+                return first_steps.remove(&room);
+            }
+            let first = first_steps[&room].clone();
+            for next in self.exits(&room) {
+                if next != from && !first_steps.contains_key(&next) {
+                    first_steps.insert(next.clone(), first.clone());
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    fn distances_from(&self, start: &str) -> HashMap<String, usize> {
+        let mut distances = HashMap::new();
+        let mut queue = VecDeque::new();
+        distances.insert(start.to_string(), 0);
+        queue.push_back(start.to_string());
+        while let Some(room) = queue.pop_front() {
+            let distance = distances[&room];
+            for next in self.exits(&room) {
+                if !distances.contains_key(&next) {
+                    distances.insert(next.clone(), distance + 1);
+                    queue.push_back(next);
+                }
+            }
+        }
+        distances
+    }
+
+    fn next_room(&mut self, index: usize, player: Option<&str>) -> Result<Option<String>, Errors> {
+        let room = self.agents[index].room.clone();
+        match self.agents[index].behaviour.clone() {
+            Behaviour::Stay => Ok(None),
+            Behaviour::Patrol(route) => {
+                if route.is_empty() {
+                    return Ok(None);
+                }
+                let agent = &mut self.agents[index];
+                if route[agent.waypoint] == room {
+                    agent.waypoint = (agent.waypoint + 1) % route.len();
+                }
+                let target = route[agent.waypoint].clone();
+                Ok(self.step_towards(&room, &target))
+            }
+            Behaviour::Wander => {
+                let exits = self.exits(&room);
+                if exits.is_empty() {
+                    return Ok(None);
+                }
+                Ok(Some(exits[self.rng.below(exits.len())].clone()))
+            }
+            Behaviour::Chase => match player {
+                Some(player) if player != room => Ok(self.step_towards(&room, player)),
+                _ => Ok(None),
+            },
+            Behaviour::Flee => {
+                let player = match player {
+                    Some(player) => player,
+                    None => return Ok(None),
+                };
+                let distances = self.distances_from(player);
+                let current = distances.get(&room).copied().unwrap_or(usize::MAX);
+                let mut best: Option<(usize, String)> = None;
+                for next in self.exits(&room) {
+                    let distance = distances.get(&next).copied().unwrap_or(usize::MAX);
+                    if distance > current && best.as_ref().is_none_or(|(d, _)| distance > *d) {
+                        best = Some((distance, next));
+                    }
+                }
+                Ok(best.map(|(_, next)| next))
+            }
+        }
+    }
+}
+
+// a corridor of five rooms, "1" to "5" from west to east
+#[cfg(test)]
+fn test_dungeon() -> Dungeon {
+    let mut dungeon = Dungeon::new();
+    for room in ["1", "2", "3", "4", "5"] {
+        dungeon.add_room(room).unwrap();
+    }
+    for (a, b) in [("1", "2"), ("2", "3"), ("3", "4"), ("4", "5")] {
+        dungeon.set_link(a, crate::Direction::East, b).unwrap();
+    }
+    dungeon
+}
+
+#[test]
+fn test_patrol_chase_and_flee() {
+    let dungeon = test_dungeon();
+    let mut simulation = Simulation::new(&dungeon, 1);
+    let guard = simulation
+        .add_agent("guard", "1", Behaviour::Patrol(vec![String::from("3"), String::from("1")]))
+        .unwrap();
+    let hound = simulation.add_agent("hound", "5", Behaviour::Chase).unwrap();
+    let rat = simulation.add_agent("rat", "2", Behaviour::Flee).unwrap();
+
+    let moves = simulation.tick(Some("1")).unwrap();
+    assert_eq!(
+        moves,
+        [
+            AgentMove { agent: guard, from: String::from("1"), to: String::from("2") },
+            AgentMove { agent: hound, from: String::from("5"), to: String::from("4") },
+            AgentMove { agent: rat, from: String::from("2"), to: String::from("3") },
+        ]
+    );
+
+    simulation.tick(Some("1")).unwrap();
+    simulation.tick(Some("1")).unwrap();
+    //the guard reached the end of the route and turned back
+    assert_eq!(simulation.agent(guard).room, "2");
+    assert_eq!(simulation.agent(hound).room, "2");
+    assert_eq!(simulation.agent(rat).room, "5");
+    assert_eq!(simulation.agents_in("2").map(|(_, a)| a.name.as_str()).collect::<Vec<_>>(), ["guard", "hound"]);
+    assert_eq!(simulation.current_tick(), 3);
+
+    assert!(matches!(simulation.tick(Some("Kitchen")), Err(Errors::UnknownRoom(_))));
+    assert!(matches!(simulation.add_agent("ghost", "Kitchen", Behaviour::Stay), Err(Errors::UnknownRoom(_))));
+}
+
+#[test]
+fn test_chasing_around_locked_and_hidden_doors() {
+    let mut dungeon = test_dungeon();
+    dungeon.add_room("Tunnel").unwrap();
+    dungeon.add_room("Cave").unwrap();
+    dungeon.set_link("1", crate::Direction::North, "Tunnel").unwrap();
+    dungeon.set_link("Tunnel", crate::Direction::East, "Cave").unwrap();
+    dungeon.set_link("Cave", crate::Direction::South, "3").unwrap();
+    dungeon.lock_link("2", crate::Direction::East).unwrap();
+    dungeon.set_hidden_link("1", crate::Direction::West, "3").unwrap();
+
+    //the short ways are locked or hidden, the hound goes around
+    let mut simulation = Simulation::new(&dungeon, 1);
+    let hound = simulation.add_agent("hound", "1", Behaviour::Chase).unwrap();
+    let rooms: Vec<String> = (0..4)
+        .map(|_| {
+            simulation.tick(Some("3")).unwrap();
+            simulation.agent(hound).room.clone()
+        })
+        .collect();
+    assert_eq!(rooms, ["Tunnel", "Cave", "3", "3"]);
+
+    //with every way locked it waits
+    dungeon.lock_link("Cave", crate::Direction::South).unwrap();
+    let mut simulation = Simulation::new(&dungeon, 1);
+    simulation.add_agent("hound", "1", Behaviour::Chase).unwrap();
+    assert!(simulation.tick(Some("3")).unwrap().is_empty());
+}
+
+#[test]
+fn test_wandering_is_deterministic() {
+    let dungeon = test_dungeon();
+    let run = |seed| {
+        let mut simulation = Simulation::new(&dungeon, seed);
+        simulation.add_agent("bat", "3", Behaviour::Wander).unwrap();
+        (0..20).map(|_| simulation.tick(None).unwrap()[0].to.clone()).collect::<Vec<_>>()
+    };
+
+    assert_eq!(run(7), run(7));
+}
+
+#[test]
+fn test_agents_by_alias() {
+    let mut dungeon = test_dungeon();
+    dungeon.add_alias("2", "Two").unwrap();
+    let mut simulation = Simulation::new(&dungeon, 1);
+    let guard = simulation.add_agent("guard", "Two", Behaviour::Patrol(vec![String::from("Two")])).unwrap();
+
+    assert_eq!(simulation.agent(guard).room, "2");
+    assert_eq!(simulation.agents_in("Two").count(), 1);
+    assert!(simulation.tick(Some("Two")).unwrap().is_empty());
+}
@@ -0,0 +1,384 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Write as _;
+use std::fs;
+use std::process;
+
+use solution::{Direction, Dungeon, Errors};
+
+// command line tool for dungeon files
+//
+//     dungeon validate <file> [--deny-warnings]
+//     dungeon fmt <file> [--check]
+//     dungeon path <file> <from> <to>
+//     dungeon stats <file>
+//     dungeon convert <file> --to dot|json
+//
+// exit codes: 0 - success, 1 - the file is invalid (or fails the requested check),
+// 2 - bad usage or the file can't be read
+
+const USAGE: &str = "usage:
+    dungeon validate <file> [--deny-warnings]
+    dungeon fmt <file> [--check]
+    dungeon path <file> <from> <to>
+    dungeon stats <file>
+    dungeon convert <file> --to dot|json";
+
+struct Loaded {
+    dungeon: Dungeon,
+    //room names in the order of the file
+    rooms: Vec<String>,
+}
+
+struct Link {
+    from: String,
+    direction: Direction,
+    to: String,
+    //the other room links back with the opposite direction
+    two_way: bool,
+}
+
+impl Loaded {
+    fn parse(text: &str) -> Result<Self, Errors> {
+        let dungeon = Dungeon::from_reader(text.as_bytes())?;
+        let rooms = dungeon.room_names().map(String::from).collect();
+        Ok(Loaded { dungeon, rooms })
+    }
+
+    fn exits(&self, room: &str) -> Vec<(Direction, String)> {
+        match self.dungeon.neighbours(room) {
+            Ok(neighbours) => neighbours.map(|(dir, next)| (dir, next.name.clone())).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn links_back(&self, from: &str, direction: Direction, to: &str) -> bool {
+        matches!(self.dungeon.get_next_room(to, direction.opposite()), Ok(Some(room)) if room.name == from)
+    }
+
+    // every two-way link once (from the room that comes first in the file) and every
+    // one-way exit on its own
+    fn links(&self) -> Vec<Link> {
+        let position: HashMap<&str, usize> = self.rooms.iter().enumerate().map(|(i, r)| (r.as_str(), i)).collect();
+        let mut result = Vec::new();
+        for room in &self.rooms {
+            for (direction, to) in self.exits(room) {
+                let two_way = self.links_back(room, direction, &to);
+                if two_way && position[to.as_str()] < position[room.as_str()] {
+                    continue;
+                }
+                if two_way && to == *room && matches!(direction, Direction::South | Direction::West) {
+                    continue;
+                }
+                result.push(Link { from: room.clone(), direction, to, two_way });
+            }
+        }
+        result
+    }
+
+    // how many one-way links start here, each going along the way back of the one before, 0 for
+    // a two-way link
+    fn one_way_chain(&self, link: &Link) -> usize {
+        if link.two_way {
+            return 0;
+        }
+        let mut length = 1;
+        let (mut room, mut direction) = (link.to.clone(), link.direction.opposite());
+        while length <= self.rooms.len() * 4 {
+            match self.dungeon.get_next_room(&room, direction) {
+                Ok(Some(back)) if !self.links_back(&room, direction, &back.name) => {
+                    room = back.name.clone();
+                    direction = direction.opposite();
+                    length += 1;
+                }
+                _ => break,
+            }
+        }
+        length
+    }
+
+    fn distances_from(&self, start: &str) -> HashMap<String, usize> {
+        let mut distances = HashMap::new();
+        let mut queue = VecDeque::new();
+        distances.insert(start.to_string(), 0);
+        queue.push_back(start.to_string());
+        while let Some(room) = queue.pop_front() {
+            let distance = distances[&room];
+            for (_, next) in self.exits(&room) {
+                if !distances.contains_key(&next) {
+                    distances.insert(next.clone(), distance + 1);
+                    queue.push_back(next);
+                }
+            }
+        }
+        distances
+    }
+
+    fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if let Some(first) = self.rooms.first() {
+            let reachable = self.distances_from(first);
+            for room in &self.rooms {
+                if !reachable.contains_key(room) {
+                    warnings.push(format!("room \"{}\" can't be reached from \"{}\"", room, first));
+                }
+            }
+        }
+        for room in &self.rooms {
+            let exits = self.exits(room);
+            if exits.is_empty() && self.rooms.len() > 1 {
+                warnings.push(format!("room \"{}\" has no exits", room));
+            }
+            for (direction, to) in exits {
+                if to == *room {
+                    warnings.push(format!("room \"{}\" leads {} to itself", room, direction));
+                } else if !self.links_back(room, direction, &to) {
+                    warnings.push(format!(
+                        "one-way link \"{}\" -> {} -> \"{}\"",
+                        room,
+                        direction,
+                        to
+                    ));
+                }
+            }
+        }
+        warnings
+    }
+
+    fn format(&self) -> String {
+        let mut out = String::from("## Rooms\n");
+        for room in &self.rooms {
+            let _ = writeln!(out, "- {}", room);
+        }
+        out.push_str("\n## Links\n");
+        //the parser always creates both sides of a link, so a one-way link has to come before
+        //the line that sets the other room's way back: the longest one-way chains first, then
+        //the two-way links, every link once
+        let mut links = self.links();
+        links.sort_by_key(|link| std::cmp::Reverse(self.one_way_chain(link)));
+        for link in links {
+            let _ = writeln!(out, "- {} -> {} -> {}", link.from, link.direction, link.to);
+        }
+        if !self.dungeon.triggers().is_empty() {
+            out.push_str("\n## Triggers\n");
+            for trigger in self.dungeon.triggers() {
+                let _ = writeln!(out, "- {}", trigger);
+            }
+        }
+        out
+    }
+
+    fn stats(&self) -> String {
+        let links = self.links();
+        let dead_ends = self.rooms.iter().filter(|r| self.exits(r).len() == 1).count();
+        let isolated = self.rooms.iter().filter(|r| self.exits(r).is_empty()).count();
+        let exits: usize = self.rooms.iter().map(|r| self.exits(r).len()).sum();
+
+        let mut components = 0;
+        let mut seen = HashSet::new();
+        let mut diameter = 0;
+        for room in &self.rooms {
+            let distances = self.distances_from(room);
+            diameter = diameter.max(distances.values().copied().max().unwrap_or(0));
+            if seen.insert(room.clone()) {
+                components += 1;
+                seen.extend(distances.into_keys());
+            }
+        }
+
+        let mut out = String::new();
+        let _ = writeln!(out, "rooms: {}", self.rooms.len());
+        let _ = writeln!(out, "links: {}", links.iter().filter(|l| l.two_way).count());
+        let _ = writeln!(out, "one-way links: {}", links.iter().filter(|l| !l.two_way).count());
+        let _ = writeln!(out, "dead ends: {}", dead_ends);
+        let _ = writeln!(out, "isolated rooms: {}", isolated);
+        let _ = writeln!(out, "connected components: {}", components);
+        let _ = writeln!(out, "longest shortest path: {}", diameter);
+        if !self.rooms.is_empty() {
+            let _ = writeln!(out, "average exits per room: {:.2}", exits as f64 / self.rooms.len() as f64);
+        }
+        out
+    }
+
+    fn to_dot(&self) -> String {
+        let mut out = String::from("digraph dungeon {\n");
+        for room in &self.rooms {
+            let _ = writeln!(out, "    \"{}\";", escape(room));
+        }
+        for link in self.links() {
+            let _ = writeln!(
+                out,
+                "    \"{}\" -> \"{}\" [label=\"{}\"{}];",
+                escape(&link.from),
+                escape(&link.to),
+                link.direction,
+                if link.two_way { ", dir=both" } else { "" }
+            );
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn to_json(&self) -> String {
+        let rooms: Vec<String> = self.rooms.iter().map(|r| format!("\"{}\"", escape(r))).collect();
+        let links: Vec<String> = self
+            .rooms
+            .iter()
+            .flat_map(|room| self.exits(room).into_iter().map(move |(dir, to)| (room, dir, to)))
+            .map(|(from, dir, to)| {
+                format!(
+                    "    {{ \"from\": \"{}\", \"direction\": \"{}\", \"to\": \"{}\" }}",
+                    escape(from),
+                    dir,
+                    escape(&to)
+                )
+            })
+            .collect();
+        format!(
+            "{{\n  \"rooms\": [{}],\n  \"links\": [\n{}\n  ]\n}}\n",
+            rooms.join(", "),
+            links.join(",\n")
+        )
+    }
+}
+
+// escaping shared by the dot and json outputs
+fn escape(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn fail(message: &str, code: i32) -> ! {
+    eprintln!("{}", message);
+    process::exit(code);
+}
+
+fn load(path: &str) -> Loaded {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => fail(&format!("error: could not read {}: {}", path, e), 2),
+    };
+    match Loaded::parse(&text) {
+        Ok(loaded) => loaded,
+        Err(e) => fail(&format!("{}: {}", path, e), 1),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+
+    match args.as_slice() {
+        ["validate", file, flags @ ..] => {
+            let loaded = load(file);
+            let warnings = loaded.warnings();
+            for warning in &warnings {
+                eprintln!("{}: warning: {}", file, warning);
+            }
+            if flags.contains(&"--deny-warnings") && !warnings.is_empty() {
+                process::exit(1);
+            }
+            println!("{}: ok", file);
+        }
+        ["fmt", file, flags @ ..] => {
+            let formatted = load(file).format();
+            if flags.contains(&"--check") {
+                let original = fs::read_to_string(file).unwrap_or_default();
+                if original != formatted {
+                    fail(&format!("{}: not formatted", file), 1);
+                }
+            } else {
+                print!("{}", formatted);
+            }
+        }
+        ["path", file, from, to] => {
+            let loaded = load(file);
+            for room in [from, to] {
+                if let Err(e) = loaded.dungeon.get_room(room) {
+                    fail(&format!("error: {}", e), 1);
+                }
+            }
+            match loaded.dungeon.find_path(from, to) {
+                Ok(Some(path)) => {
+                    let names: Vec<&str> = path.iter().map(|room| room.name.as_str()).collect();
+                    println!("{}", names.join(" -> "));
+                }
+                Ok(None) => fail(&format!("no path from \"{}\" to \"{}\"", from, to), 1),
+                Err(e) => fail(&format!("error: {}", e), 1),
+            }
+        }
+        ["stats", file] => print!("{}", load(file).stats()),
+        ["convert", file, "--to", "dot"] => print!("{}", load(file).to_dot()),
+        ["convert", file, "--to", "json"] => print!("{}", load(file).to_json()),
+        _ => fail(USAGE, 2),
+    }
+}
+
+#[cfg(test)]
+const TEST_FILE: &str = "## Rooms
+- Entrance
+- Hallway
+- Closet
+
+## Links
+- Hallway -> West -> Entrance
+- Hallway -> North -> Closet";
+
+#[test]
+fn test_format_and_lint() {
+    let loaded = Loaded::parse(TEST_FILE).unwrap();
+
+    assert_eq!(
+        loaded.format(),
+        "## Rooms\n- Entrance\n- Hallway\n- Closet\n\n## Links\n- Entrance -> East -> Hallway\n- Hallway -> North -> Closet\n"
+    );
+    //formatting is idempotent
+    let formatted = Loaded::parse(&loaded.format()).unwrap();
+    assert_eq!(formatted.format(), loaded.format());
+
+    let with_triggers = format!("{}\n\n## Triggers\n- on enter Closet => say It's dark.", TEST_FILE);
+    assert!(Loaded::parse(&with_triggers).unwrap().format().ends_with("\n\n## Triggers\n- on enter Closet => say It's dark.\n"));
+
+    assert_eq!(loaded.warnings(), Vec::<String>::new());
+
+    //the way back of a one-way link is another room's two-way link, each is written once
+    let one_way = Loaded::parse("## Rooms\n- A\n- B\n- C\n## Links\n- A -> North -> B\n- C -> North -> B").unwrap();
+    assert_eq!(one_way.format(), "## Rooms\n- A\n- B\n- C\n\n## Links\n- A -> North -> B\n- B -> South -> C\n");
+    let formatted = Loaded::parse(&one_way.format()).unwrap();
+    assert_eq!(formatted.format(), one_way.format());
+    assert_eq!(formatted.dungeon.get_next_room("B", Direction::South).unwrap().unwrap().name, "C");
+
+    //a chain of one-way links, each taking the way back of the one before
+    let chain = "## Rooms\n- D\n- C\n- B\n- A\n## Links\n- A -> North -> B\n- B -> South -> C\n- D -> South -> C";
+    let chain = Loaded::parse(chain).unwrap();
+    assert_eq!(
+        chain.format(),
+        "## Rooms\n- D\n- C\n- B\n- A\n\n## Links\n- A -> North -> B\n- B -> South -> C\n- D -> South -> C\n"
+    );
+    let formatted = Loaded::parse(&chain.format()).unwrap();
+    for room in ["A", "B", "C", "D"] {
+        assert_eq!(formatted.exits(room), chain.exits(room));
+    }
+}
+
+#[test]
+fn test_stats_and_json() {
+    let loaded = Loaded::parse(TEST_FILE).unwrap();
+    let stats = loaded.stats();
+
+    assert!(stats.contains("rooms: 3\n"));
+    assert!(stats.contains("links: 2\n"));
+    assert!(stats.contains("dead ends: 2\n"));
+    assert!(stats.contains("longest shortest path: 2\n"));
+    assert!(loaded.to_json().contains("{ \"from\": \"Entrance\", \"direction\": \"East\", \"to\": \"Hallway\" }"));
+}
@@ -0,0 +1,230 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process;
+
+use solution::session::{MoveOutcome, Session};
+use solution::{Direction, Dungeon, Errors};
+
+// text adventure over a dungeon file
+//
+//     dungeon_repl <file> <start room> [--script]
+//
+// with --script the commands are read from stdin without a prompt and the first failing
+// command makes the process exit with a non-zero code
+
+const HELP: &str = "commands: go <direction>, look, exits, path to <room>, map, help, quit";
+
+struct Repl<'a> {
+    dungeon: &'a Dungeon,
+    session: Session<'a>,
+}
+
+enum Outcome {
+    Continue,
+    Failed,
+    Quit,
+}
+
+impl<'a> Repl<'a> {
+    fn new(dungeon: &'a Dungeon, start: &str) -> Result<Self, Errors> {
+        Ok(Repl { dungeon, session: Session::new(dungeon, start)? })
+    }
+
+    fn current(&self) -> &'a str {
+        &self.session.current_room().name
+    }
+
+    fn execute<W: Write>(&mut self, command: &str, out: &mut W) -> io::Result<Outcome> {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        match words.as_slice() {
+            [] => Ok(Outcome::Continue),
+            ["quit"] | ["exit"] => Ok(Outcome::Quit),
+            ["help"] => {
+                writeln!(out, "{}", HELP)?;
+                Ok(Outcome::Continue)
+            }
+            ["look"] => {
+                writeln!(out, "you are in {}", self.current())?;
+                writeln!(out, "{}", self.session.describe_exits())?;
+                Ok(Outcome::Continue)
+            }
+            ["exits"] => {
+                writeln!(out, "{}", self.session.describe_exits())?;
+                Ok(Outcome::Continue)
+            }
+            ["map"] => {
+                //only what the player has discovered so far
+                write!(out, "{}", self.session.known_map().render(self.current()))?;
+                Ok(Outcome::Continue)
+            }
+            ["go", dir] | [dir @ ("north" | "south" | "east" | "west" | "n" | "s" | "e" | "w")] => {
+                let direction = match dir.parse::<Direction>() {
+                    Ok(direction) => direction,
+                    Err(_) => {
+                        writeln!(out, "error: unknown direction \"{}\"", dir)?;
+                        return Ok(Outcome::Failed);
+                    }
+                };
+                match self.session.move_dir(direction) {
+                    MoveOutcome::Moved => {
+                        writeln!(out, "you are in {}", self.current())?;
+                        for message in self.session.take_messages() {
+                            writeln!(out, "{}", message)?;
+                        }
+                        Ok(Outcome::Continue)
+                    }
+                    MoveOutcome::Wall => {
+                        writeln!(out, "error: you can't go {} from {}", direction.to_string().to_lowercase(), self.current())?;
+                        Ok(Outcome::Failed)
+                    }
+                    MoveOutcome::Locked => {
+                        writeln!(out, "error: the door {} of {} is locked", direction.to_string().to_lowercase(), self.current())?;
+                        Ok(Outcome::Failed)
+                    }
+                }
+            }
+            ["path", "to", ..] => {
+                let target = words[2..].join(" ");
+                if let Err(e) = self.dungeon.get_room(&target) {
+                    writeln!(out, "error: {}", e.describe())?;
+                    return Ok(Outcome::Failed);
+                }
+                match self.dungeon.find_path(self.current(), &target) {
+                    Ok(Some(path)) => {
+                        let names: Vec<&str> = path.iter().map(|room| room.name.as_str()).collect();
+                        writeln!(out, "{}", names.join(" -> "))?;
+                        Ok(Outcome::Continue)
+                    }
+                    Ok(None) => {
+                        writeln!(out, "error: there is no way to {}", target)?;
+                        Ok(Outcome::Failed)
+                    }
+                    Err(e) => {
+                        writeln!(out, "error: {}", e.describe())?;
+                        Ok(Outcome::Failed)
+                    }
+                }
+            }
+            _ => {
+                writeln!(out, "error: unknown command \"{}\" ({})", command.trim(), HELP)?;
+                Ok(Outcome::Failed)
+            }
+        }
+    }
+
+    // returns false if any command failed
+    fn run<R: BufRead, W: Write>(&mut self, input: R, out: &mut W, interactive: bool) -> io::Result<bool> {
+        let mut all_ok = true;
+        if interactive {
+            write!(out, "> ")?;
+            out.flush()?;
+        }
+        for line in input.lines() {
+            match self.execute(&line?, out)? {
+                Outcome::Continue => {}
+                Outcome::Failed => {
+                    all_ok = false;
+                    if !interactive {
+                        break;
+                    }
+                }
+                Outcome::Quit => break,
+            }
+            if interactive {
+                write!(out, "> ")?;
+                out.flush()?;
+            }
+        }
+        Ok(all_ok)
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let script = args.iter().any(|a| a == "--script");
+    let positional: Vec<&String> = args.iter().filter(|a| *a != "--script").collect();
+    if positional.len() != 2 {
+        eprintln!("usage: dungeon_repl <file> <start room> [--script]");
+        process::exit(2);
+    }
+
+    let file = match File::open(positional[0]) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("error: could not open {}: {}", positional[0], e);
+            process::exit(2);
+        }
+    };
+    let dungeon = match Dungeon::from_reader(BufReader::new(file)) {
+        Ok(dungeon) => dungeon,
+        Err(e) => {
+            eprintln!("error: {}", e.describe());
+            process::exit(2);
+        }
+    };
+    let mut repl = match Repl::new(&dungeon, positional[1]) {
+        Ok(repl) => repl,
+        Err(e) => {
+            eprintln!("error: {}", e.describe());
+            process::exit(2);
+        }
+    };
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    match repl.run(stdin.lock(), &mut stdout.lock(), !script) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(2);
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_dungeon() -> Dungeon {
+    let mut dungeon = Dungeon::new();
+    dungeon.add_room("Entrance").unwrap();
+    dungeon.add_room("Hallway").unwrap();
+    dungeon.add_room("Treasure Room").unwrap();
+    dungeon.set_link("Entrance", Direction::East, "Hallway").unwrap();
+    dungeon.set_link("Hallway", Direction::South, "Treasure Room").unwrap();
+    dungeon
+}
+
+#[cfg(test)]
+fn run_script(dungeon: &Dungeon, script: &str) -> (bool, String) {
+    let mut repl = Repl::new(dungeon, "Entrance").unwrap();
+    let mut out = Vec::new();
+    let ok = repl.run(script.as_bytes(), &mut out, false).unwrap();
+    (ok, String::from_utf8(out).unwrap())
+}
+
+#[test]
+fn test_scripted_walk() {
+    let dungeon = test_dungeon();
+    let (ok, out) = run_script(&dungeon, "look\ngo east\nsouth\npath to Entrance\n");
+
+    assert!(ok);
+    assert_eq!(
+        out,
+        "you are in Entrance\neast -> Hallway\n\
+         you are in Hallway\n\
+         you are in Treasure Room\n\
+         Treasure Room -> Hallway -> Entrance\n"
+    );
+}
+
+#[test]
+fn test_scripted_errors() {
+    let dungeon = test_dungeon();
+
+    let (ok, out) = run_script(&dungeon, "go north\nlook\n");
+    assert!(!ok);
+    assert_eq!(out, "error: you can't go north from Entrance\n");
+
+    let (ok, out) = run_script(&dungeon, "path to Kitchen\n");
+    assert!(!ok);
+    assert_eq!(out, "error: there is no room called \"Kitchen\"\n");
+}
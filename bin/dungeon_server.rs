@@ -0,0 +1,658 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::process;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+
+use solution::session::{MoveOutcome, Session};
+use solution::shared::SharedDungeon;
+use solution::{Direction, Dungeon};
+
+// several players exploring one dungeon over localhost
+//
+//     dungeon_server <file> <start room> [--port <port>] [--websocket]
+//
+// clients send one command per line and get lines back. The first line is the player's name,
+// after that: go <direction> (or just the direction), look, exits, who, say <text>,
+// path to <room>, help, quit. Errors start with "error: ", things other players do start
+// with "* ". With --websocket clients connect with a websocket handshake instead, and every
+// text frame holds commands and every line of the answer comes in a frame of its own.
+//
+// every player has a session of their own, doors one player unlocks stay locked for the
+// others. The server only listens on 127.0.0.1, the port is 4000 unless it's given.
+
+const HELP: &str = "commands: go <direction>, look, exits, who, say <text>, path to <room>, help, quit";
+
+const DEFAULT_PORT: u16 = 4000;
+
+// frames bigger than this close the connection
+const MAX_FRAME: u64 = 64 * 1024;
+
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+// what the thread writing to a client sends
+enum Message {
+    Line(String),
+    Pong(Vec<u8>),
+    Close,
+}
+
+struct Player {
+    room: String,
+    outbox: Sender<Message>,
+}
+
+struct Server {
+    dungeon: SharedDungeon,
+    start: String,
+    websocket: bool,
+    players: Mutex<HashMap<String, Player>>,
+}
+
+impl Server {
+    fn players(&self) -> std::sync::MutexGuard<'_, HashMap<String, Player>> {
+        self.players.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // everybody in the room but `except`
+    fn tell_room(&self, room: &str, except: &str, text: &str) {
+        for (name, player) in self.players().iter() {
+            if player.room == room && name != except {
+                let _ = player.outbox.send(Message::Line(text.to_string()));
+            }
+        }
+    }
+
+    fn others_in(&self, room: &str, except: &str) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .players()
+            .iter()
+            .filter(|(name, player)| player.room == room && *name != except)
+            .map(|(name, _)| name.clone())
+            .collect();
+        names.sort();
+        names
+    }
+}
+
+// sha-1 and base64, all the websocket handshake needs of them
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend((data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut words = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            words[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in words.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let next = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = next;
+        }
+        for (value, add) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (bytes, value) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, byte)| n | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, WEBSOCKET_GUID).as_bytes()))
+}
+
+// reads the upgrade request and answers it
+fn handshake(reader: &mut BufReader<TcpStream>, stream: &mut TcpStream) -> io::Result<()> {
+    let mut key = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+    match key {
+        Some(key) => write!(
+            stream,
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+            accept_key(&key)
+        ),
+        None => {
+            write!(stream, "HTTP/1.1 400 Bad Request\r\n\r\n")?;
+            Err(io::Error::new(io::ErrorKind::InvalidData, "not a websocket handshake"))
+        }
+    }
+}
+
+// the opcode and the unmasked payload of a frame, `None` at the end of the stream
+fn read_frame<R: Read>(reader: &mut R) -> io::Result<Option<(u8, Vec<u8>)>> {
+    let mut head = [0u8; 2];
+    match reader.read_exact(&mut head) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let length = match head[1] & 0x7f {
+        126 => {
+            let mut bytes = [0u8; 2];
+            reader.read_exact(&mut bytes)?;
+            u16::from_be_bytes(bytes) as u64
+        }
+        127 => {
+            let mut bytes = [0u8; 8];
+            reader.read_exact(&mut bytes)?;
+            u64::from_be_bytes(bytes)
+        }
+        length => length as u64,
+    };
+    if length > MAX_FRAME {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "websocket frame too big"));
+    }
+    let mut mask = [0u8; 4];
+    if head[1] & 0x80 != 0 {
+        reader.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0u8; length as usize];
+    reader.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok(Some((head[0] & 0x0f, payload)))
+}
+
+// a whole unmasked frame, the way servers send them
+fn write_frame<W: Write>(writer: &mut W, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        length if length < 126 => frame.push(length as u8),
+        length if length <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend((length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend((length as u64).to_be_bytes());
+        }
+    }
+    frame.extend(payload);
+    writer.write_all(&frame)
+}
+
+fn write_messages(mut stream: TcpStream, websocket: bool, outbox: Receiver<Message>) -> io::Result<()> {
+    for message in outbox {
+        match (message, websocket) {
+            (Message::Line(line), false) => writeln!(stream, "{}", line)?,
+            (Message::Line(line), true) => write_frame(&mut stream, 0x1, line.as_bytes())?,
+            (Message::Pong(data), _) => write_frame(&mut stream, 0xA, &data)?,
+            (Message::Close, true) => {
+                write_frame(&mut stream, 0x8, &[])?;
+                break;
+            }
+            (Message::Close, false) => break,
+        }
+        stream.flush()?;
+    }
+    stream.shutdown(Shutdown::Both)
+}
+
+// the commands of one client, line by line whatever the transport
+struct Commands {
+    reader: BufReader<TcpStream>,
+    websocket: bool,
+    pending: VecDeque<String>,
+}
+
+impl Commands {
+    fn next(&mut self, outbox: &Sender<Message>) -> io::Result<Option<String>> {
+        if !self.websocket {
+            let mut line = String::new();
+            return match self.reader.read_line(&mut line)? {
+                0 => Ok(None),
+                _ => Ok(Some(line.trim().to_string())),
+            };
+        }
+        while self.pending.is_empty() {
+            match read_frame(&mut self.reader)? {
+                None | Some((0x8, _)) => return Ok(None),
+                Some((0x9, data)) => {
+                    let _ = outbox.send(Message::Pong(data));
+                }
+                Some((0x1, text)) => {
+                    let text = String::from_utf8_lossy(&text).into_owned();
+                    self.pending.extend(text.lines().map(|line| line.trim().to_string()));
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(self.pending.pop_front())
+    }
+}
+
+struct Client<'a> {
+    server: &'a Server,
+    name: String,
+    session: Session<'a>,
+    outbox: Sender<Message>,
+}
+
+impl Client<'_> {
+    fn say(&self, text: &str) {
+        let _ = self.outbox.send(Message::Line(text.to_string()));
+    }
+
+    fn current(&self) -> String {
+        self.session.current_room().name.clone()
+    }
+
+    fn look(&self) {
+        self.say(&format!("you are in {}", self.current()));
+        self.say(&self.session.describe_exits());
+        let others = self.server.others_in(&self.current(), &self.name);
+        if !others.is_empty() {
+            self.say(&format!("also here: {}", others.join(", ")));
+        }
+    }
+
+    fn go(&mut self, direction: Direction) {
+        let from = self.current();
+        match self.session.move_dir(direction) {
+            MoveOutcome::Moved => {
+                let to = self.current();
+                self.server.tell_room(&from, &self.name, &format!("* {} leaves {}", self.name, direction.to_string().to_lowercase()));
+                if let Some(player) = self.server.players().get_mut(&self.name) {
+                    player.room = to.clone();
+                }
+                self.server.tell_room(&to, &self.name, &format!("* {} arrives", self.name));
+                self.look();
+                for message in self.session.take_messages() {
+                    self.say(&message);
+                }
+            }
+            MoveOutcome::Wall => self.say(&format!("error: you can't go {} from {}", direction.to_string().to_lowercase(), from)),
+            MoveOutcome::Locked => {
+                self.say(&format!("error: the door {} of {} is locked", direction.to_string().to_lowercase(), from))
+            }
+        }
+    }
+
+    fn path_to(&self, dungeon: &Dungeon, target: &str) {
+        match dungeon.find_path(&self.current(), target) {
+            Ok(Some(path)) => {
+                let names: Vec<&str> = path.iter().map(|room| room.name.as_str()).collect();
+                self.say(&names.join(" -> "));
+            }
+            Ok(None) => self.say(&format!("error: there is no way to {}", target)),
+            Err(e) => self.say(&format!("error: {}", e.describe())),
+        }
+    }
+
+    // false once the client is done
+    fn execute(&mut self, dungeon: &Dungeon, command: &str) -> bool {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        match words.as_slice() {
+            [] => {}
+            ["quit"] | ["exit"] => return false,
+            ["help"] => self.say(HELP),
+            ["look"] => self.look(),
+            ["exits"] => self.say(&self.session.describe_exits()),
+            ["who"] => {
+                let others = self.server.others_in(&self.current(), &self.name);
+                if others.is_empty() {
+                    self.say("nobody else is here");
+                } else {
+                    self.say(&format!("also here: {}", others.join(", ")));
+                }
+            }
+            ["say", ..] => {
+                let text = command.trim_start()[3..].trim();
+                self.server.tell_room(&self.current(), &self.name, &format!("{} says: {}", self.name, text));
+                self.say(&format!("you say: {}", text));
+            }
+            ["go", dir] | [dir @ ("north" | "south" | "east" | "west" | "n" | "s" | "e" | "w")] => {
+                match dir.parse::<Direction>() {
+                    Ok(direction) => self.go(direction),
+                    Err(e) => self.say(&format!("error: {}", e.describe())),
+                }
+            }
+            ["path", "to", ..] => self.path_to(dungeon, &words[2..].join(" ")),
+            _ => self.say(&format!("error: unknown command \"{}\" ({})", command.trim(), HELP)),
+        }
+        true
+    }
+}
+
+fn serve_client(server: &Server, stream: TcpStream) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    if server.websocket {
+        handshake(&mut reader, &mut writer)?;
+    }
+    let (outbox, receiver) = mpsc::channel();
+    let websocket = server.websocket;
+    let writer = thread::spawn(move || write_messages(writer, websocket, receiver));
+    let mut commands = Commands { reader, websocket, pending: VecDeque::new() };
+
+    let _ = outbox.send(Message::Line(String::from("welcome, what's your name?")));
+    let name = loop {
+        let name = match commands.next(&outbox)? {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        if name.is_empty() {
+            let _ = outbox.send(Message::Line(String::from("error: the name can't be empty")));
+            continue;
+        }
+        let mut players = server.players();
+        if players.contains_key(&name) {
+            let _ = outbox.send(Message::Line(format!("error: {} is already playing", name)));
+            continue;
+        }
+        players.insert(name.clone(), Player { room: server.start.clone(), outbox: outbox.clone() });
+        break name;
+    };
+
+    let dungeon = server.dungeon.snapshot();
+    let session = match Session::new(&dungeon, &server.start) {
+        Ok(session) => session,
+        Err(e) => {
+            server.players().remove(&name);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, e.to_string()));
+        }
+    };
+    let mut client = Client { server, name: name.clone(), session, outbox: outbox.clone() };
+    server.tell_room(&server.start, &name, &format!("* {} arrives", name));
+    client.look();
+
+    let result = loop {
+        match commands.next(&outbox) {
+            Ok(Some(command)) if client.execute(&dungeon, &command) => {}
+            Ok(_) => break Ok(()),
+            Err(e) => break Err(e),
+        }
+    };
+
+    let room = client.current();
+    server.players().remove(&name);
+    server.tell_room(&room, &name, &format!("* {} leaves", name));
+    let _ = outbox.send(Message::Close);
+    drop(client);
+    drop(outbox);
+    let _ = writer.join();
+    result
+}
+
+fn serve(server: Arc<Server>, listener: TcpListener) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                continue;
+            }
+        };
+        let server = server.clone();
+        thread::spawn(move || {
+            if let Err(e) = serve_client(&server, stream) {
+                eprintln!("error: {}", e);
+            }
+        });
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let websocket = args.iter().any(|a| a == "--websocket");
+    let mut port = DEFAULT_PORT;
+    let mut positional = Vec::new();
+    let mut words = args.iter().filter(|a| *a != "--websocket");
+    while let Some(word) = words.next() {
+        if word == "--port" {
+            port = match words.next().and_then(|p| p.parse().ok()) {
+                Some(port) => port,
+                None => {
+                    eprintln!("error: --port needs a port number");
+                    process::exit(2);
+                }
+            };
+        } else {
+            positional.push(word);
+        }
+    }
+    if positional.len() != 2 {
+        eprintln!("usage: dungeon_server <file> <start room> [--port <port>] [--websocket]");
+        process::exit(2);
+    }
+
+    let file = match File::open(positional[0]) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("error: could not open {}: {}", positional[0], e);
+            process::exit(2);
+        }
+    };
+    let dungeon = match Dungeon::from_reader(BufReader::new(file)) {
+        Ok(dungeon) => dungeon,
+        Err(e) => {
+            eprintln!("error: {}", e.describe());
+            process::exit(2);
+        }
+    };
+    //the start can be an alias, players are always kept in a room by its own name
+    let start = match dungeon.get_room(positional[1]) {
+        Ok(room) => room.name.clone(),
+        Err(e) => {
+            eprintln!("error: {}", e.describe());
+            process::exit(2);
+        }
+    };
+
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("error: could not listen on port {}: {}", port, e);
+            process::exit(2);
+        }
+    };
+    println!("listening on 127.0.0.1:{}", port);
+    let server = Server {
+        dungeon: SharedDungeon::new(dungeon),
+        start,
+        websocket,
+        players: Mutex::new(HashMap::new()),
+    };
+    serve(Arc::new(server), listener);
+}
+
+#[cfg(test)]
+fn start_server(websocket: bool) -> std::net::SocketAddr {
+    let mut dungeon = Dungeon::new();
+    dungeon.add_room("Entrance").unwrap();
+    dungeon.add_room("Hallway").unwrap();
+    dungeon.add_room("Treasure Room").unwrap();
+    dungeon.set_link("Entrance", Direction::East, "Hallway").unwrap();
+    dungeon.set_link("Hallway", Direction::South, "Treasure Room").unwrap();
+
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = Server {
+        dungeon: SharedDungeon::new(dungeon),
+        start: String::from("Entrance"),
+        websocket,
+        players: Mutex::new(HashMap::new()),
+    };
+    thread::spawn(move || serve(Arc::new(server), listener));
+    address
+}
+
+// a scripted player on the line protocol
+#[cfg(test)]
+struct TestClient {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+#[cfg(test)]
+impl TestClient {
+    fn join(address: std::net::SocketAddr, name: &str) -> TestClient {
+        let stream = TcpStream::connect(address).unwrap();
+        stream.set_read_timeout(Some(std::time::Duration::from_secs(5))).unwrap();
+        let reader = BufReader::new(stream.try_clone().unwrap());
+        let mut client = TestClient { stream, reader };
+        client.expect(&["welcome, what's your name?"]);
+        client.send(name);
+        client
+    }
+
+    fn send(&mut self, line: &str) {
+        writeln!(self.stream, "{}", line).unwrap();
+    }
+
+    fn expect(&mut self, lines: &[&str]) {
+        for expected in lines {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            assert_eq!(line.trim_end(), *expected);
+        }
+    }
+}
+
+#[test]
+fn test_players_meet_and_chat() {
+    let address = start_server(false);
+    let mut alice = TestClient::join(address, "alice");
+    alice.expect(&["you are in Entrance", "east -> Hallway"]);
+    let mut bob = TestClient::join(address, "bob");
+    bob.expect(&["you are in Entrance", "east -> Hallway", "also here: alice"]);
+    alice.expect(&["* bob arrives"]);
+
+    let mut impostor = TestClient::join(address, "bob");
+    impostor.expect(&["error: bob is already playing"]);
+
+    bob.send("say hi there");
+    bob.expect(&["you say: hi there"]);
+    alice.expect(&["bob says: hi there"]);
+
+    alice.send("e");
+    alice.expect(&["you are in Hallway", "south -> Treasure Room, west -> Entrance"]);
+    bob.expect(&["* alice leaves east"]);
+    bob.send("who");
+    bob.expect(&["nobody else is here"]);
+    bob.send("path to Treasure Room");
+    bob.expect(&["Entrance -> Hallway -> Treasure Room"]);
+    bob.send("go north");
+    bob.expect(&["error: you can't go north from Entrance"]);
+
+    bob.send("go east");
+    alice.expect(&["* bob arrives"]);
+    bob.expect(&["you are in Hallway", "south -> Treasure Room, west -> Entrance", "also here: alice"]);
+    bob.send("quit");
+    alice.expect(&["* bob leaves"]);
+}
+
+#[test]
+fn test_websocket_accept_key() {
+    //the example of RFC 6455
+    assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    assert_eq!(base64(b"ab"), "YWI=");
+}
+
+#[test]
+fn test_websocket_clients() {
+    let address = start_server(true);
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream.set_read_timeout(Some(std::time::Duration::from_secs(5))).unwrap();
+    write!(
+        stream,
+        "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n"
+    )
+    .unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut response = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line.trim().is_empty() {
+            break;
+        }
+        response.push(line.trim().to_string());
+    }
+    assert_eq!(response[0], "HTTP/1.1 101 Switching Protocols");
+    assert!(response.contains(&String::from("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=")));
+
+    //clients mask what they send
+    let mut send = |text: &str| {
+        let mask = [1u8, 2, 3, 4];
+        let mut frame = vec![0x81, 0x80 | text.len() as u8];
+        frame.extend(mask);
+        frame.extend(text.bytes().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+        stream.write_all(&frame).unwrap();
+    };
+    let mut receive = || {
+        let (opcode, payload) = read_frame(&mut reader).unwrap().unwrap();
+        (opcode, String::from_utf8(payload).unwrap())
+    };
+
+    assert_eq!(receive(), (0x1, String::from("welcome, what's your name?")));
+    send("carol");
+    assert_eq!(receive().1, "you are in Entrance");
+    assert_eq!(receive().1, "east -> Hallway");
+    send("go east\nexits");
+    assert_eq!(receive().1, "you are in Hallway");
+    assert_eq!(receive().1, "south -> Treasure Room, west -> Entrance");
+    assert_eq!(receive().1, "south -> Treasure Room, west -> Entrance");
+    send("quit");
+    assert_eq!(receive().0, 0x8);
+}
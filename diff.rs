@@ -0,0 +1,243 @@
+use std::fmt;
+
+use crate::{Dungeon, Errors, Exit, ExitKind};
+
+// what changed between two versions of a dungeon, for reviews and as a patch
+//
+//     + room Kitchen
+//     - room Pantry
+//     ? room Pantry -> Kitchen
+//     + link Kitchen -> West -> Hall
+//     ~ link Hall -> East -> Pantry => Kitchen
+//     - link Hall -> North -> Cellar
+//
+// links are compared one side at a time, so a changed two-way link shows up from both of its
+// rooms. The exits of a removed room aren't listed, they go away with the room. A `?` line is a
+// removed and an added room with the same exits, probably a rename; a patch still removes and
+// adds them. Locks and triggers aren't part of the diff.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkChange {
+    pub room: String,
+    pub kind: ExitKind,
+    pub before: Option<Exit>,
+    pub after: Option<Exit>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DungeonDiff {
+    pub added_rooms: Vec<String>,
+    pub removed_rooms: Vec<String>,
+    //(old name, new name)
+    pub renamed_candidates: Vec<(String, String)>,
+    pub changed_links: Vec<LinkChange>,
+}
+
+// the exits of a room, with the room's own name replaced so renamed rooms can be compared
+fn exit_signature(dungeon: &Dungeon, room_name: &str) -> Vec<(ExitKind, String, bool)> {
+    let mut exits: Vec<(ExitKind, String, bool)> = dungeon.rooms[room_name]
+        .next_to
+        .iter()
+        .map(|e| (e.kind.clone(), if e.to == room_name { String::new() } else { e.to.clone() }, e.hidden))
+        .collect();
+    exits.sort_by_key(|(kind, _, _)| kind.to_string());
+    exits
+}
+
+impl Dungeon {
+    // the changes that turn this dungeon into `other`
+    pub fn diff(&self, other: &Dungeon) -> DungeonDiff {
+        let mut diff = DungeonDiff {
+            added_rooms: other.room_names().filter(|name| !self.rooms.contains_key(*name)).map(String::from).collect(),
+            removed_rooms: self.room_names().filter(|name| !other.rooms.contains_key(*name)).map(String::from).collect(),
+            ..DungeonDiff::default()
+        };
+
+        for removed in &diff.removed_rooms {
+            let signature = exit_signature(self, removed);
+            let renamed = diff.added_rooms.iter().find(|added| {
+                !signature.is_empty()
+                    && exit_signature(other, added) == signature
+                    && !diff.renamed_candidates.iter().any(|(_, new)| new == *added)
+            });
+            if let Some(added) = renamed {
+                diff.renamed_candidates.push((removed.clone(), added.clone()));
+            }
+        }
+
+        for room in other.rooms() {
+            let old = self.rooms.get(&room.name);
+            let old_exits = old.into_iter().flat_map(|r| r.next_to.iter());
+            let kinds = room.next_to.iter().chain(old_exits.filter(|e| room.next_to.get(&e.kind).is_none()));
+            for kind in kinds.map(|e| e.kind.clone()).collect::<Vec<_>>() {
+                let before = old.and_then(|r| r.next_to.get(&kind)).cloned();
+                let after = room.next_to.get(&kind).cloned();
+                if before != after {
+                    diff.changed_links.push(LinkChange { room: room.name.clone(), kind, before, after });
+                }
+            }
+        }
+        diff
+    }
+}
+
+impl DungeonDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_rooms.is_empty() && self.removed_rooms.is_empty() && self.changed_links.is_empty()
+    }
+
+    // checks that the dungeon is the one the diff was made from (as far as the diff touches it)
+    // before changing anything, and changes it in one transaction
+    pub fn apply(&self, dungeon: &mut Dungeon) -> Result<(), Errors> {
+        for name in &self.added_rooms {
+            if dungeon.rooms.contains_key(name) || dungeon.resolve_room_name(name).is_some() {
+                return Err(Errors::PatchConflict(format!("room \"{}\" is already there", name)));
+            }
+        }
+        for name in &self.removed_rooms {
+            if !dungeon.rooms.contains_key(name) {
+                return Err(Errors::PatchConflict(format!("room \"{}\" isn't there", name)));
+            }
+        }
+        for change in &self.changed_links {
+            let current = dungeon.rooms.get(&change.room).and_then(|r| r.next_to.get(&change.kind));
+            let new_room = self.added_rooms.contains(&change.room);
+            if !new_room && (!dungeon.rooms.contains_key(&change.room) || current != change.before.as_ref()) {
+                return Err(Errors::PatchConflict(format!("exit {} -> {} has changed", change.room, change.kind)));
+            }
+        }
+
+        dungeon.transaction(|dungeon| {
+            for name in &self.added_rooms {
+                dungeon.add_room(name)?;
+            }
+            for change in &self.changed_links {
+                match &change.after {
+                    Some(exit) => dungeon.set_exit(&change.room, exit.kind.clone(), &exit.to, exit.hidden),
+                    None => {
+                        dungeon.remove_exit(&change.room, &change.kind);
+                    }
+                }
+            }
+            for name in &self.removed_rooms {
+                dungeon.remove_room(name)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+// `Hall -> East -> Kitchen`, with `(hidden)` for secret passages
+struct ExitLine<'a>(&'a str, &'a Exit);
+
+impl fmt::Display for ExitLine<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {} -> {}", self.0, self.1.kind, self.1.to)?;
+        if self.1.hidden {
+            write!(f, " (hidden)")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DungeonDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for name in &self.added_rooms {
+            writeln!(f, "+ room {}", name)?;
+        }
+        for name in &self.removed_rooms {
+            writeln!(f, "- room {}", name)?;
+        }
+        for (old, new) in &self.renamed_candidates {
+            writeln!(f, "? room {} -> {}", old, new)?;
+        }
+        for change in &self.changed_links {
+            match (&change.before, &change.after) {
+                (None, Some(after)) => writeln!(f, "+ link {}", ExitLine(&change.room, after))?,
+                (Some(before), None) => writeln!(f, "- link {}", ExitLine(&change.room, before))?,
+                (Some(before), Some(after)) => {
+                    write!(f, "~ link {} => {}", ExitLine(&change.room, before), after.to)?;
+                    writeln!(f, "{}", if after.hidden { " (hidden)" } else { "" })?;
+                }
+                (None, None) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn test_dungeons() -> (Dungeon, Dungeon) {
+    let before = Dungeon::from_reader(
+        "## Rooms\n- Hall\n- Pantry\n- Cellar\n## Links\n- Hall -> East -> Pantry\n- Hall -> North -> Cellar".as_bytes(),
+    )
+    .unwrap();
+    let mut after = Dungeon::from_reader("## Rooms\n- Hall\n- Kitchen\n- Cellar\n## Links\n- Hall -> East -> Kitchen".as_bytes())
+        .unwrap();
+    after.set_hidden_link("Cellar", crate::Direction::South, "Kitchen").unwrap();
+    (before, after)
+}
+
+#[test]
+fn test_diffing_dungeons() {
+    let (before, after) = test_dungeons();
+    let diff = before.diff(&after);
+
+    assert_eq!(
+        diff.to_string(),
+        "+ room Kitchen
+- room Pantry
+~ link Hall -> East -> Pantry => Kitchen
+- link Hall -> North -> Cellar
++ link Kitchen -> North -> Cellar (hidden)
++ link Kitchen -> West -> Hall
+~ link Cellar -> South -> Hall => Kitchen (hidden)
+"
+    );
+    assert!(diff.renamed_candidates.is_empty());
+    assert!(before.diff(&before).is_empty());
+
+    //same exits under a new name
+    let mut renamed = before.clone();
+    renamed.add_room("Larder").unwrap();
+    renamed.set_link("Hall", crate::Direction::East, "Larder").unwrap();
+    renamed.remove_room("Pantry").unwrap();
+    assert_eq!(before.diff(&renamed).renamed_candidates, [(String::from("Pantry"), String::from("Larder"))]);
+}
+
+#[test]
+fn test_applying_a_diff() {
+    let (before, after) = test_dungeons();
+    let diff = before.diff(&after);
+
+    let mut patched = before.clone();
+    diff.apply(&mut patched).unwrap();
+    assert!(patched.diff(&after).is_empty());
+    assert_eq!(patched.room_names().collect::<Vec<_>>(), ["Hall", "Cellar", "Kitchen"]);
+
+    //a second time the rooms are already there, and nothing changes
+    assert!(matches!(diff.apply(&mut patched), Err(Errors::PatchConflict(_))));
+    assert!(patched.diff(&after).is_empty());
+
+    let mut moved = before.clone();
+    moved.set_link("Hall", crate::Direction::East, "Cellar").unwrap();
+    assert!(matches!(diff.apply(&mut moved), Err(Errors::PatchConflict(_))));
+}
+
+#[test]
+fn test_applying_a_diff_that_collides_with_names() {
+    let (before, after) = test_dungeons();
+    let mut aliased = before.clone();
+    aliased.add_alias("Pantry", "Kitchen").unwrap();
+    assert!(matches!(before.diff(&after).apply(&mut aliased), Err(Errors::PatchConflict(_))));
+    assert!(aliased.diff(&before).is_empty());
+
+    //the second room only collides with the first once that one is added
+    let mut both = after.clone();
+    both.add_room("KITCHEN").unwrap();
+    both.set_link("KITCHEN", crate::Direction::West, "Cellar").unwrap();
+    let mut folded = before.clone();
+    folded.set_name_matching(crate::names::NameMatching::CaseFolded).unwrap();
+    assert!(matches!(before.diff(&both).apply(&mut folded), Err(Errors::DuplicateRoom(_))));
+    assert!(folded.diff(&before).is_empty());
+}
@@ -0,0 +1,196 @@
+use std::fmt;
+
+use crate::{Dungeon, ExitKind, Room};
+
+// telling editors, renderers and caches what changed in a dungeon
+//
+// every change of a room or an exit sends one event to every observer, right after the change
+// and in the order the changes happen. A link is two exits, so `set_link` sends a `LinkSet`
+// for each side. Removing a room sends `LinkRemoved` for the exits of other rooms that led to
+// it and then `RoomRemoved`, its own exits go with it. Undo, redo and failed transactions send
+// the events that turn the dungeon back, a rename that is undone comes as a removed and an
+// added room. Locks and triggers don't send events.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DungeonEvent {
+    RoomAdded { room: String },
+    RoomRemoved { room: String },
+    //`previous` is the room the exit led to before, `None` for a new exit
+    LinkSet { room: String, kind: ExitKind, to: String, hidden: bool, previous: Option<String> },
+    LinkRemoved { room: String, kind: ExitKind, to: String },
+    RoomRenamed { from: String, to: String },
+}
+
+impl fmt::Display for DungeonEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DungeonEvent::RoomAdded { room } => write!(f, "room {} added", room),
+            DungeonEvent::RoomRemoved { room } => write!(f, "room {} removed", room),
+            DungeonEvent::LinkSet { room, kind, to, previous, .. } => match previous {
+                Some(previous) => write!(f, "link {} -> {} -> {} replaced {}", room, kind, to, previous),
+                None => write!(f, "link {} -> {} -> {} set", room, kind, to),
+            },
+            DungeonEvent::LinkRemoved { room, kind, to } => write!(f, "link {} -> {} -> {} removed", room, kind, to),
+            DungeonEvent::RoomRenamed { from, to } => write!(f, "room {} renamed to {}", from, to),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObserverId(usize);
+
+type Observer = Box<dyn FnMut(&DungeonEvent) + Send + Sync>;
+
+#[derive(Default)]
+pub(crate) struct Observers {
+    next_id: usize,
+    observers: Vec<(ObserverId, Observer)>,
+}
+
+// a copy of a dungeon starts without observers, they were watching the original
+impl Clone for Observers {
+    fn clone(&self) -> Self {
+        Observers::default()
+    }
+}
+
+impl Observers {
+    pub(crate) fn subscribe(&mut self, observer: impl FnMut(&DungeonEvent) + Send + Sync + 'static) -> ObserverId {
+        let id = ObserverId(self.next_id);
+        self.next_id += 1;
+        self.observers.push((id, Box::new(observer)));
+        id
+    }
+
+    pub(crate) fn unsubscribe(&mut self, id: ObserverId) -> bool {
+        let count = self.observers.len();
+        self.observers.retain(|(other, _)| *other != id);
+        self.observers.len() != count
+    }
+}
+
+impl Dungeon {
+    pub fn subscribe(&mut self, observer: impl FnMut(&DungeonEvent) + Send + Sync + 'static) -> ObserverId {
+        self.observers.subscribe(observer)
+    }
+
+    // false when there is no observer with the id
+    pub fn unsubscribe(&mut self, id: ObserverId) -> bool {
+        self.observers.unsubscribe(id)
+    }
+
+    // the event is only made when somebody is listening
+    pub(crate) fn emit(&mut self, event: impl FnOnce() -> DungeonEvent) {
+        if self.observers.observers.is_empty() {
+            return;
+        }
+        let event = event();
+        for (_, observer) in &mut self.observers.observers {
+            observer(&event);
+        }
+    }
+
+    // the events for a room that was replaced with another version of it behind the journal's
+    // back, rooms that appear go first and rooms that disappear last
+    pub(crate) fn emit_room_changes(&mut self, changes: &[(String, Option<Room>)]) {
+        if self.observers.observers.is_empty() {
+            return;
+        }
+        let mut events = Vec::new();
+        for (name, before) in changes {
+            if before.is_none() && self.rooms.contains_key(name) {
+                events.push(DungeonEvent::RoomAdded { room: name.clone() });
+            }
+        }
+        for (name, before) in changes {
+            let after = match self.rooms.get(name) {
+                Some(after) => after,
+                None => continue,
+            };
+            for exit in after.next_to.iter() {
+                let previous = before.as_ref().and_then(|room| room.next_to.get(&exit.kind));
+                if previous != Some(exit) {
+                    events.push(DungeonEvent::LinkSet {
+                        room: name.clone(),
+                        kind: exit.kind.clone(),
+                        to: exit.to.clone(),
+                        hidden: exit.hidden,
+                        previous: previous.map(|previous| previous.to.clone()),
+                    });
+                }
+            }
+            for exit in before.iter().flat_map(|room| room.next_to.iter()) {
+                if after.next_to.get(&exit.kind).is_none() {
+                    events.push(DungeonEvent::LinkRemoved { room: name.clone(), kind: exit.kind.clone(), to: exit.to.clone() });
+                }
+            }
+        }
+        for (name, before) in changes {
+            if before.is_some() && !self.rooms.contains_key(name) {
+                events.push(DungeonEvent::RoomRemoved { room: name.clone() });
+            }
+        }
+        for event in events {
+            self.emit(|| event);
+        }
+    }
+}
+
+#[cfg(test)]
+fn watch(dungeon: &mut Dungeon) -> std::sync::Arc<std::sync::Mutex<Vec<String>>> {
+    let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let log = seen.clone();
+    dungeon.subscribe(move |event| log.lock().unwrap().push(event.to_string()));
+    seen
+}
+
+#[test]
+fn test_events() {
+    use crate::Direction;
+
+    let mut dungeon = Dungeon::new();
+    let seen = watch(&mut dungeon);
+    dungeon.add_room("Hall").unwrap();
+    dungeon.add_room("Cellar").unwrap();
+    dungeon.add_room("Attic").unwrap();
+    dungeon.set_link("Hall", Direction::North, "Cellar").unwrap();
+    dungeon.set_link("Hall", Direction::North, "Attic").unwrap();
+    dungeon.rename_room("Attic", "Loft").unwrap();
+    dungeon.remove_room("Loft").unwrap();
+
+    assert_eq!(
+        *seen.lock().unwrap(),
+        [
+            "room Hall added",
+            "room Cellar added",
+            "room Attic added",
+            "link Hall -> North -> Cellar set",
+            "link Cellar -> South -> Hall set",
+            "link Hall -> North -> Attic replaced Cellar",
+            "link Attic -> South -> Hall set",
+            "room Attic renamed to Loft",
+            "link Hall -> North -> Loft removed",
+            "room Loft removed",
+        ]
+    );
+}
+
+#[test]
+fn test_undo_events() {
+    let mut dungeon = Dungeon::new();
+    dungeon.add_room("Hall").unwrap();
+    dungeon.start_journal();
+    let seen = watch(&mut dungeon);
+    dungeon.add_room("Cellar").unwrap();
+    dungeon.set_named_exit("Hall", "trapdoor", "Cellar", true).unwrap();
+    seen.lock().unwrap().clear();
+
+    dungeon.undo();
+    dungeon.undo();
+    assert_eq!(*seen.lock().unwrap(), ["link Hall -> trapdoor -> Cellar removed", "room Cellar removed"]);
+
+    //nobody listens any more
+    let id = dungeon.subscribe(|_| {});
+    assert!(dungeon.unsubscribe(id));
+    assert!(!dungeon.unsubscribe(id));
+}
@@ -0,0 +1,176 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::{Direction, Dungeon, Errors, ExitKind, ALL_DIRECTIONS};
+
+// what a single explorer knows about the dungeon
+// entering a room explores it: the room and all of its exits become known, together with the
+// names of the rooms behind them, which are seen but not explored until entered. Hidden exits
+// aren't seen, a session adds the ones the player has gone through
+
+#[derive(Debug, Clone, Default)]
+pub struct KnownMap {
+    //known rooms in the order they were discovered
+    order: Vec<String>,
+    seen: HashSet<String>,
+    exits: HashMap<String, Vec<(Direction, String)>>,
+}
+
+impl KnownMap {
+    pub fn new() -> Self {
+        KnownMap::default()
+    }
+
+    pub fn explore(&mut self, dungeon: &Dungeon, room_name: &str) -> Result<(), Errors> {
+        let room = dungeon.get_room(room_name)?;
+        let mut exits = Vec::new();
+        for direction in ALL_DIRECTIONS {
+            match room.next_to.get(&ExitKind::Compass(direction)) {
+                Some(exit) if !exit.hidden => exits.push((direction, exit.to.clone())),
+                _ => {}
+            }
+        }
+        self.record(&room.name, exits);
+        Ok(())
+    }
+
+    // explores a room whose exits were looked up by the caller
+    pub(crate) fn record(&mut self, room_name: &str, exits: Vec<(Direction, String)>) {
+        self.see(room_name);
+        for (_, next) in &exits {
+            self.see(next);
+        }
+        self.exits.insert(room_name.to_string(), exits);
+    }
+
+    fn see(&mut self, room_name: &str) {
+        if self.seen.insert(room_name.to_string()) {
+            self.order.push(room_name.to_string());
+        }
+    }
+
+    pub fn knows(&self, room_name: &str) -> bool {
+        self.seen.contains(room_name)
+    }
+
+    pub fn is_explored(&self, room_name: &str) -> bool {
+        self.exits.contains_key(room_name)
+    }
+
+    pub fn known_rooms(&self) -> &[String] {
+        &self.order
+    }
+
+    // None for rooms that haven't been explored yet
+    pub fn known_exits(&self, room_name: &str) -> Option<&[(Direction, String)]> {
+        self.exits.get(room_name).map(|exits| exits.as_slice())
+    }
+
+    // shortest path using only known exits, rooms the explorer doesn't know about are
+    // reported as unknown even if they exist in the dungeon
+    pub fn find_path(&self, start_room_name: &str, end_room_name: &str) -> Result<Option<Vec<String>>, Errors> {
+        for name in [start_room_name, end_room_name] {
+            if !self.knows(name) {
+                return Err(Errors::UnknownRoom(name.to_string()));
+            }
+        }
+
+        let mut previous = HashMap::<&str, &str>::new();
+        let mut queue = VecDeque::new();
+        previous.insert(start_room_name, start_room_name);
+        queue.push_back(start_room_name);
+        while let Some(room) = queue.pop_front() {
+            if room == end_room_name {
+                let mut path = vec![room.to_string()];
+                let mut current = room;
+                while current != start_room_name {
+                    current = previous[current];
+                    path.push(current.to_string());
+                }
+                path.reverse();
+                return Ok(Some(path));
+            }
+            for (_, next) in self.exits.get(room).into_iter().flatten() {
+                if !previous.contains_key(next.as_str()) {
+                    previous.insert(next, room);
+                    queue.push_back(next);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // one line per known room, `current` is marked with a star
+    pub fn render(&self, current: &str) -> String {
+        let mut out = String::new();
+        for room in &self.order {
+            let marker = if room == current { "*" } else { " " };
+            match self.exits.get(room) {
+                Some(exits) => {
+                    let exits: Vec<String> = exits
+                        .iter()
+                        .map(|(dir, next)| format!("{} -> {}", dir.to_string().to_lowercase(), next))
+                        .collect();
+                    out.push_str(&format!("{} {}: {}\n", marker, room, exits.join(", ")));
+                }
+                None => out.push_str(&format!("{} {}: unexplored\n", marker, room)),
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+fn test_dungeon() -> Dungeon {
+    let mut dungeon = Dungeon::new();
+    dungeon.add_room("Entrance").unwrap();
+    dungeon.add_room("Hallway").unwrap();
+    dungeon.add_room("Closet").unwrap();
+    dungeon.add_room("Treasure Room").unwrap();
+    dungeon.set_link("Entrance", Direction::East, "Hallway").unwrap();
+    dungeon.set_link("Hallway", Direction::North, "Closet").unwrap();
+    dungeon.set_link("Closet", Direction::East, "Treasure Room").unwrap();
+    dungeon
+}
+
+#[test]
+fn test_exploring() {
+    let dungeon = test_dungeon();
+    let mut known = KnownMap::new();
+    known.explore(&dungeon, "Entrance").unwrap();
+
+    assert!(known.knows("Hallway"));
+    assert!(!known.is_explored("Hallway"));
+    assert!(!known.knows("Closet"));
+    assert_eq!(known.render("Entrance"), "* Entrance: east -> Hallway\n  Hallway: unexplored\n");
+
+    known.explore(&dungeon, "Hallway").unwrap();
+    assert_eq!(known.known_rooms(), ["Entrance", "Hallway", "Closet"]);
+    assert_eq!(
+        known.known_exits("Hallway").unwrap(),
+        [(Direction::North, String::from("Closet")), (Direction::West, String::from("Entrance"))]
+    );
+}
+
+#[test]
+fn test_finding_known_paths() {
+    let dungeon = test_dungeon();
+    let mut known = KnownMap::new();
+    known.explore(&dungeon, "Entrance").unwrap();
+    known.explore(&dungeon, "Hallway").unwrap();
+
+    assert_eq!(known.find_path("Entrance", "Closet").unwrap().unwrap(), ["Entrance", "Hallway", "Closet"]);
+    //the closet hasn't been explored, so nothing leads out of it yet
+    assert!(known.find_path("Closet", "Entrance").unwrap().is_none());
+    assert!(matches!(known.find_path("Entrance", "Treasure Room"), Err(Errors::UnknownRoom(_))));
+}
+
+#[test]
+fn test_hidden_exits_are_not_seen() {
+    let mut dungeon = test_dungeon();
+    dungeon.set_hidden_link("Entrance", Direction::South, "Treasure Room").unwrap();
+    let mut known = KnownMap::new();
+    known.explore(&dungeon, "Entrance").unwrap();
+
+    assert!(!known.knows("Treasure Room"));
+    assert_eq!(known.known_exits("Entrance").unwrap(), [(Direction::East, String::from("Hallway"))]);
+}
@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use crate::parser::Parser;
+use crate::{Dungeon, Errors};
+
+// dungeon files made of other dungeon files
+//
+//     ## Rooms
+//     !include wings/east.dungeon
+//     !include wings/west.dungeon as west
+//     - Hall
+//
+//     ## Links
+//     - Hall -> East -> east::Entrance
+//     - Hall -> West -> west::Entrance
+//
+// an include goes in the rooms section and adds every room, link, lock and trigger of the other
+// file, with the room names prefixed by `<namespace>::`. The namespace is the file name without
+// its extension unless the include gives one, and includes inside included files nest their
+// namespaces (`east::cellar::Stairs`). Paths are found by a `Resolver`, relative to the file
+// with the include.
+
+pub const NAMESPACE_SEPARATOR: &str = "::";
+
+pub trait Resolver {
+    // the name of the file `path` points to, when it's included from the file `from`
+    fn resolve(&self, from: &str, path: &str) -> String;
+
+    fn open(&self, name: &str) -> io::Result<Box<dyn BufRead + '_>>;
+}
+
+// files on disk, paths relative to the directory of the including file
+pub struct FileResolver;
+
+impl Resolver for FileResolver {
+    fn resolve(&self, from: &str, path: &str) -> String {
+        let directory = Path::new(from).parent().unwrap_or_else(|| Path::new(""));
+        directory.join(path).to_string_lossy().into_owned()
+    }
+
+    fn open(&self, name: &str) -> io::Result<Box<dyn BufRead + '_>> {
+        Ok(Box::new(BufReader::new(File::open(name)?)))
+    }
+}
+
+// files kept in memory, with `/` between directories
+#[derive(Debug, Clone, Default)]
+pub struct MemoryResolver {
+    files: HashMap<String, String>,
+}
+
+impl MemoryResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: &str, text: &str) {
+        self.files.insert(name.to_string(), text.to_string());
+    }
+}
+
+impl Resolver for MemoryResolver {
+    fn resolve(&self, from: &str, path: &str) -> String {
+        let mut parts: Vec<&str> = from.split('/').collect();
+        parts.pop();
+        for part in path.split('/') {
+            match part {
+                "" | "." => {}
+                ".." => {
+                    parts.pop();
+                }
+                part => parts.push(part),
+            }
+        }
+        parts.join("/")
+    }
+
+    fn open(&self, name: &str) -> io::Result<Box<dyn BufRead + '_>> {
+        match self.files.get(name) {
+            Some(text) => Ok(Box::new(text.as_bytes())),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, format!("no file called \"{}\"", name))),
+        }
+    }
+}
+
+// the namespace of an include without `as`
+fn default_namespace(path: &str) -> &str {
+    let file = path.rsplit(['/', '\\']).next().unwrap_or(path);
+    file.split('.').next().unwrap_or(file)
+}
+
+// adds the `!include` line of `file` to an error that happened in an included file
+fn included_from(error: Errors, included: &str, file: &str, line_number: usize) -> Errors {
+    match error {
+        Errors::IncludeError { file: inner, mut included_from, error } => {
+            included_from.insert(0, (file.to_string(), line_number));
+            Errors::IncludeError { file: inner, included_from, error }
+        }
+        error => Errors::IncludeError {
+            file: included.to_string(),
+            included_from: vec![(file.to_string(), line_number)],
+            error: Box::new(error),
+        },
+    }
+}
+
+// `stack` holds the files that are being read, the last one includes `name`
+fn read(resolver: &dyn Resolver, name: &str, stack: &mut Vec<String>) -> Result<Dungeon, Errors> {
+    if stack.iter().any(|file| file == name) {
+        let mut cycle: Vec<String> = stack.iter().skip_while(|file| *file != name).cloned().collect();
+        cycle.push(name.to_string());
+        return Err(Errors::IncludeCycle(cycle));
+    }
+    let mut reader = resolver.open(name).map_err(Errors::IoError)?;
+    stack.push(name.to_string());
+
+    let mut parser = Parser::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match reader.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(error) => return Err(parser.read_error(error)),
+        }
+        let include = match parser.line(&line)? {
+            Some(include) => include,
+            None => continue,
+        };
+
+        let line_number = parser.line_number();
+        let included = resolver.resolve(name, include.path);
+        let namespace = include.namespace.unwrap_or_else(|| default_namespace(include.path)).to_string();
+        let other = read(resolver, &included, stack).map_err(|e| included_from(e, &included, name, line_number))?;
+        let rename = |room: &str| format!("{}{}{}", namespace, NAMESPACE_SEPARATOR, room);
+        //a room that is already there is an error of the include line
+        parser
+            .dungeon_mut()
+            .absorb(&other, rename, false)
+            .map_err(|e| included_from(e, &included, name, line_number))?;
+    }
+
+    stack.pop();
+    parser.finish()
+}
+
+impl Dungeon {
+    pub fn from_resolver(resolver: &dyn Resolver, name: &str) -> Result<Self, Errors> {
+        read(resolver, name, &mut Vec::new())
+    }
+
+    // a file on disk, with its includes
+    pub fn from_file(path: &str) -> Result<Self, Errors> {
+        Self::from_resolver(&FileResolver, path)
+    }
+}
+
+#[cfg(test)]
+fn test_files() -> MemoryResolver {
+    let mut files = MemoryResolver::new();
+    files.insert(
+        "castle.dungeon",
+        "## Rooms
+!include wings/east.dungeon
+!include wings/east.dungeon as annex
+- Hall
+## Links
+- Hall -> East -> east::Entrance
+- Hall -> West -> annex::Entrance",
+    );
+    files.insert(
+        "wings/east.dungeon",
+        "## Rooms
+!include ../cellar.dungeon
+- Entrance
+## Links
+- Entrance -> North -> cellar::Stairs
+## Triggers
+- on enter Entrance => lock Entrance -> West",
+    );
+    files.insert("cellar.dungeon", "## Rooms\n- Stairs\n## Links");
+    files
+}
+
+#[test]
+fn test_including_files() {
+    let dungeon = Dungeon::from_resolver(&test_files(), "castle.dungeon").unwrap();
+
+    assert_eq!(
+        dungeon.room_names().collect::<Vec<_>>(),
+        [
+            "east::cellar::Stairs",
+            "east::Entrance",
+            "annex::cellar::Stairs",
+            "annex::Entrance",
+            "Hall"
+        ]
+    );
+    let next = |room, dir| dungeon.get_next_room(room, dir).unwrap().unwrap().name.clone();
+    assert_eq!(next("annex::Entrance", crate::Direction::North), "annex::cellar::Stairs");
+    assert_eq!(next("east::Entrance", crate::Direction::West), "Hall");
+    assert_eq!(dungeon.triggers()[1].to_string(), "on enter annex::Entrance => lock annex::Entrance -> West");
+}
+
+#[test]
+fn test_include_errors() {
+    //the error is in the middle file, the chain starts at the top one
+    let mut files = test_files();
+    files.insert("wings/east.dungeon", &files.files["wings/east.dungeon"].replace("-> North ->", "-> Down ->"));
+    let error = Dungeon::from_resolver(&files, "castle.dungeon").err().unwrap();
+    assert!(matches!(
+        &error,
+        Errors::IncludeError { file, included_from, error }
+            if file == "wings/east.dungeon"
+                && included_from == &[(String::from("castle.dungeon"), 2)]
+                && matches!(**error, Errors::DirectionParseError(_))
+    ));
+    assert_eq!(error.to_string(), "wings/east.dungeon: unknown direction \"Down\", included from castle.dungeon line 2");
+
+    let mut files = test_files();
+    files.insert("cellar.dungeon", "## Rooms\n!include wings/east.dungeon\n## Links");
+    let error = Dungeon::from_resolver(&files, "castle.dungeon").err().unwrap();
+    assert_eq!(
+        error.to_string(),
+        "wings/east.dungeon: include cycle: wings/east.dungeon -> cellar.dungeon -> wings/east.dungeon, \
+         included from cellar.dungeon line 2, included from wings/east.dungeon line 2, included from castle.dungeon line 2"
+    );
+
+    let mut files = test_files();
+    files.insert("cellar.dungeon", "## Rooms\n- Stairs\n## Links\nStairs");
+    let error = Dungeon::from_resolver(&files, "castle.dungeon").err().unwrap();
+    assert_eq!(error.line_number(), Some(4));
+
+    files.insert("castle.dungeon", "## Rooms\n!include missing.dungeon");
+    let error = Dungeon::from_resolver(&files, "castle.dungeon").err().unwrap();
+    assert!(matches!(&error, Errors::IncludeError { error, .. } if matches!(**error, Errors::IoError(_))));
+
+    //a room that is already there, from the same namespace used twice or from a room of the
+    //including file
+    let mut files = test_files();
+    files.insert("castle.dungeon", &files.files["castle.dungeon"].replace(" as annex", " as east"));
+    let error = Dungeon::from_resolver(&files, "castle.dungeon").err().unwrap();
+    assert_eq!(
+        error.to_string(),
+        "wings/east.dungeon: duplicate room \"east::cellar::Stairs\", included from castle.dungeon line 3"
+    );
+    let mut files = test_files();
+    files.insert("wings/east.dungeon", &files.files["wings/east.dungeon"].replace("## Rooms\n", "## Rooms\n- cellar::Stairs\n"));
+    let error = Dungeon::from_resolver(&files, "castle.dungeon").err().unwrap();
+    assert_eq!(
+        error.to_string(),
+        "cellar.dungeon: duplicate room \"cellar::Stairs\", included from wings/east.dungeon line 3, \
+         included from castle.dungeon line 2"
+    );
+
+    //includes need a resolver
+    assert!(matches!(
+        Dungeon::from_reader("## Rooms\n!include cellar.dungeon\n## Links".as_bytes()),
+        Err(Errors::LineParseError { line_number: 2 })
+    ));
+}
@@ -0,0 +1,296 @@
+use std::mem;
+
+use crate::triggers::Trigger;
+use crate::{Direction, Dungeon, Errors, Room};
+
+// undo, redo and transactions
+//
+// every edit of a dungeon goes through `Dungeon::edit`, which keeps the state of everything
+// the edit touches as it was before (rooms, locks, the triggers and the room order). Putting
+// that state back is the undo, and the state it replaces is the redo. Edits are only kept
+// while the journal is on, so loading a file doesn't fill it up. With the journal off a failing
+// edit is only undone inside `transaction`, so methods that make several changes and can fail
+// halfway run them in a transaction, everything else checks before changing anything.
+
+// what an edit touched, as it was before the edit
+#[derive(Clone, Default)]
+pub(crate) struct Step {
+    //`None` for a room that didn't exist
+    rooms: Vec<(String, Option<Room>)>,
+    locks: Vec<((String, Direction), bool)>,
+    triggers: Option<Vec<Trigger>>,
+    order: Option<Vec<String>>,
+}
+
+impl Step {
+    fn is_empty(&self) -> bool {
+        self.rooms.is_empty() && self.locks.is_empty() && self.triggers.is_none() && self.order.is_none()
+    }
+
+    // `inner` happened after this step, where both touched something this step has the older state
+    fn merge(&mut self, inner: Step) {
+        for (name, room) in inner.rooms {
+            if !self.rooms.iter().any(|(n, _)| *n == name) {
+                self.rooms.push((name, room));
+            }
+        }
+        for (key, locked) in inner.locks {
+            if !self.locks.iter().any(|(k, _)| *k == key) {
+                self.locks.push((key, locked));
+            }
+        }
+        self.triggers = self.triggers.take().or(inner.triggers);
+        self.order = self.order.take().or(inner.order);
+    }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct Journal {
+    enabled: bool,
+    //what the running edit touched so far, `None` outside of edits
+    current: Option<Step>,
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+}
+
+impl Dungeon {
+    // from now on every edit can be undone
+    pub fn start_journal(&mut self) {
+        self.journal.enabled = true;
+    }
+
+    // forgets the edits there are and stops keeping new ones
+    pub fn stop_journal(&mut self) {
+        self.journal = Journal::default();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.journal.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.journal.redo.is_empty()
+    }
+
+    // false when there is nothing to undo, or when it's called in the middle of an edit
+    pub fn undo(&mut self) -> bool {
+        if self.journal.current.is_some() {
+            return false;
+        }
+        match self.journal.undo.pop() {
+            Some(step) => {
+                let redo = self.put_back(step);
+                self.journal.redo.push(redo);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn redo(&mut self) -> bool {
+        if self.journal.current.is_some() {
+            return false;
+        }
+        match self.journal.redo.pop() {
+            Some(step) => {
+                let undo = self.put_back(step);
+                self.journal.undo.push(undo);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // runs the edits in `edit` as one, when one of them fails all of them are undone and the
+    // error is returned. With the journal on it is a single step to undo
+    pub fn transaction<T>(&mut self, edit: impl FnOnce(&mut Dungeon) -> Result<T, Errors>) -> Result<T, Errors> {
+        self.run_edit(true, edit)
+    }
+
+    // every method that changes the dungeon runs its changes in here
+    pub(crate) fn edit<T>(&mut self, edit: impl FnOnce(&mut Dungeon) -> Result<T, Errors>) -> Result<T, Errors> {
+        self.run_edit(false, edit)
+    }
+
+    fn run_edit<T>(
+        &mut self,
+        always: bool,
+        edit: impl FnOnce(&mut Dungeon) -> Result<T, Errors>,
+    ) -> Result<T, Errors> {
+        if !always && !self.journal.enabled && self.journal.current.is_none() {
+            return edit(self);
+        }
+        let outer = self.journal.current.replace(Step::default());
+        let result = edit(self);
+        let step = self.journal.current.take().unwrap_or_default();
+        self.journal.current = outer;
+        if result.is_err() {
+            self.put_back(step);
+            return result;
+        }
+        match &mut self.journal.current {
+            Some(outer) => outer.merge(step),
+            None if self.journal.enabled && !step.is_empty() => {
+                self.journal.undo.push(step);
+                self.journal.redo.clear();
+            }
+            None => {}
+        }
+        result
+    }
+
+    // restores the state kept in `step`, and returns the state it replaced
+    fn put_back(&mut self, step: Step) -> Step {
+        let mut replaced = Step::default();
+        for (name, room) in step.rooms {
+            let current = match room {
+                Some(room) => self.rooms.insert(name.clone(), room),
+                None => self.rooms.remove(&name),
+            };
+            self.adjacency.update(&name, self.rooms.get(&name));
+            replaced.rooms.push((name, current));
+        }
+        self.adjacency.changed();
+        for (key, locked) in step.locks {
+            let current = if locked { !self.locked.insert(key.clone()) } else { self.locked.remove(&key) };
+            replaced.locks.push((key, current));
+        }
+        replaced.triggers = step.triggers.map(|triggers| mem::replace(&mut self.triggers, triggers));
+        replaced.order = step.order.map(|order| mem::replace(&mut self.order, order));
+        self.emit_room_changes(&replaced.rooms);
+        replaced
+    }
+
+    // the only ways to change the parts of a dungeon, each one keeps the old state for the
+    // running edit
+
+    pub(crate) fn touch_room(&mut self, room_name: &str) {
+        if let Some(step) = &mut self.journal.current {
+            if !step.rooms.iter().any(|(name, _)| name == room_name) {
+                step.rooms.push((room_name.to_string(), self.rooms.get(room_name).cloned()));
+            }
+        }
+    }
+
+    pub(crate) fn room_mut(&mut self, room_name: &str) -> Option<&mut Room> {
+        self.touch_room(room_name);
+        self.rooms.get_mut(room_name)
+    }
+
+    pub(crate) fn set_locked(&mut self, room_name: &str, direction: Direction, locked: bool) {
+        let key = (room_name.to_string(), direction);
+        if let Some(step) = &mut self.journal.current {
+            if !step.locks.iter().any(|(k, _)| *k == key) {
+                step.locks.push((key.clone(), self.locked.contains(&key)));
+            }
+        }
+        if locked {
+            self.locked.insert(key);
+        } else {
+            self.locked.remove(&key);
+        }
+    }
+
+    pub(crate) fn triggers_mut(&mut self) -> &mut Vec<Trigger> {
+        if let Some(step) = &mut self.journal.current {
+            if step.triggers.is_none() {
+                step.triggers = Some(self.triggers.clone());
+            }
+        }
+        &mut self.triggers
+    }
+
+    pub(crate) fn order_mut(&mut self) -> &mut Vec<String> {
+        if let Some(step) = &mut self.journal.current {
+            if step.order.is_none() {
+                step.order = Some(self.order.clone());
+            }
+        }
+        &mut self.order
+    }
+}
+
+#[cfg(test)]
+fn test_dungeon() -> Dungeon {
+    let mut dungeon = Dungeon::new();
+    dungeon.add_room("Entrance").unwrap();
+    dungeon.add_room("Hallway").unwrap();
+    dungeon.set_link("Entrance", Direction::North, "Hallway").unwrap();
+    dungeon
+}
+
+#[cfg(test)]
+fn describe(dungeon: &Dungeon) -> Vec<String> {
+    let mut lines: Vec<String> = dungeon.links().map(|(from, dir, to)| format!("{} -> {} -> {}", from, dir, to)).collect();
+    lines.extend(dungeon.room_names().map(String::from));
+    lines.extend(dungeon.triggers().iter().map(|trigger| trigger.to_string()));
+    let mut locks: Vec<String> = dungeon.locked.iter().map(|(room, dir)| format!("locked {} {}", room, dir)).collect();
+    locks.sort();
+    lines.extend(locks);
+    lines
+}
+
+#[test]
+fn test_undo_and_redo() {
+    let mut dungeon = test_dungeon();
+    dungeon.start_journal();
+    let start = describe(&dungeon);
+
+    dungeon.add_room("Cellar").unwrap();
+    dungeon.set_link("Entrance", Direction::North, "Cellar").unwrap();
+    dungeon.lock_link("Cellar", Direction::South).unwrap();
+    let edited = describe(&dungeon);
+
+    while dungeon.undo() {}
+    assert_eq!(describe(&dungeon), start);
+    assert!(!dungeon.can_undo());
+
+    while dungeon.redo() {}
+    assert_eq!(describe(&dungeon), edited);
+
+    dungeon.undo();
+    dungeon.remove_room("Hallway").unwrap();
+    assert!(!dungeon.can_redo());
+    dungeon.undo();
+    assert_eq!(dungeon.room_names().collect::<Vec<_>>(), ["Entrance", "Hallway", "Cellar"]);
+}
+
+#[test]
+fn test_transactions() {
+    let mut dungeon = test_dungeon();
+    dungeon.start_journal();
+    let start = describe(&dungeon);
+
+    let result = dungeon.transaction(|d| {
+        d.add_room("Cellar")?;
+        d.set_link("Hallway", Direction::East, "Cellar")?;
+        d.set_link("Cellar", Direction::East, "Attic")
+    });
+    assert!(matches!(result, Err(Errors::UnknownRoom(name)) if name == "Attic"));
+    assert_eq!(describe(&dungeon), start);
+    assert!(!dungeon.can_undo());
+
+    dungeon
+        .transaction(|d| {
+            d.add_room("Cellar")?;
+            d.set_link("Hallway", Direction::East, "Cellar")?;
+            //a failed transaction inside one only undoes its own edits
+            let inner = d.transaction(|d| {
+                d.remove_room("Entrance")?;
+                d.add_room("Hallway")
+            });
+            assert!(inner.is_err());
+            assert!(d.get_room("Entrance").is_ok());
+            d.lock_link("Cellar", Direction::West)
+        })
+        .unwrap();
+    assert!(dungeon.is_locked("Hallway", Direction::East));
+    assert!(dungeon.undo());
+    assert_eq!(describe(&dungeon), start);
+
+    //without the journal transactions still roll back
+    let mut dungeon = test_dungeon();
+    assert!(dungeon.transaction(|d| d.add_room("Cellar").and_then(|_| d.add_room("Cellar"))).is_err());
+    assert!(dungeon.get_room("Cellar").is_err());
+    assert!(!dungeon.undo());
+}
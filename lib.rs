@@ -1,4 +1,31 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+use events::DungeonEvent;
+
+mod adjacency;
+pub mod agents;
+pub mod diff;
+pub mod events;
+pub mod fog;
+pub mod include;
+mod journal;
+pub mod maze;
+pub mod merge;
+// the normalised matching needs `unicode-normalization` as an optional dependency enabled by
+// the `unicode` feature
+pub mod names;
+mod parser;
+pub mod save;
+pub mod session;
+pub mod shared;
+pub mod triggers;
+pub mod world;
+// needs `serde` (with the derive feature) as an optional dependency enabled by the feature
+#[cfg(feature = "serde")]
+mod serde_impls;
+// needs `rhai` as an optional dependency enabled by the feature
+#[cfg(feature = "scripting")]
+pub mod scripting;
  
 #[derive(Debug)]
 pub enum Errors {
@@ -7,126 +34,383 @@ pub enum Errors {
     IoError(std::io::Error),
     LineParseError { line_number: usize },
     DirectionParseError(String),
+    //a saved game refers to something that isn't in the dungeon anymore
+    SaveMismatch(String),
+    UnsupportedSaveVersion(u32),
+    //a script failed to compile, failed while running or hit one of its limits
+    ScriptError(String),
+    DuplicateLevel(String),
+    UnknownLevel(String),
+    //an error in an included file, with the file and every `!include` line that led to it,
+    //starting from the top file
+    IncludeError { file: String, included_from: Vec<(String, usize)>, error: Box<Errors> },
+    //the files of the cycle, the first one again at the end
+    IncludeCycle(Vec<String>),
+    //a patch doesn't fit the dungeon it's applied to
+    PatchConflict(String),
+}
+
+impl std::fmt::Display for Errors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Errors::DuplicateRoom(name) => write!(f, "duplicate room \"{}\"", name),
+            Errors::UnknownRoom(name) => write!(f, "unknown room \"{}\"", name),
+            Errors::IoError(e) => write!(f, "read error: {}", e),
+            Errors::LineParseError { line_number } => write!(f, "syntax error on line {}", line_number),
+            Errors::DirectionParseError(dir) => write!(f, "unknown direction \"{}\"", dir),
+            Errors::SaveMismatch(what) => write!(f, "the saved game doesn't match the dungeon: \"{}\"", what),
+            Errors::UnsupportedSaveVersion(version) => write!(f, "unsupported save version {}", version),
+            Errors::ScriptError(message) => write!(f, "script error: {}", message),
+            Errors::DuplicateLevel(name) => write!(f, "duplicate level \"{}\"", name),
+            Errors::UnknownLevel(name) => write!(f, "unknown level \"{}\"", name),
+            Errors::IncludeError { file, included_from, error } => {
+                write!(f, "{}: {}", file, error)?;
+                for (including, line_number) in included_from.iter().rev() {
+                    write!(f, ", included from {} line {}", including, line_number)?;
+                }
+                Ok(())
+            }
+            Errors::IncludeCycle(files) => write!(f, "include cycle: {}", files.join(" -> ")),
+            Errors::PatchConflict(what) => write!(f, "the patch doesn't fit the dungeon: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for Errors {}
+
+impl Errors {
+    //the error as the binaries tell it to players
+    pub fn describe(&self) -> String {
+        match self {
+            Errors::UnknownRoom(name) => format!("there is no room called \"{}\"", name),
+            other => other.to_string(),
+        }
+    }
+
+    //`IoError` keeps the original error, wrapped in a `ReadError` when we know the line
+    pub(crate) fn read_error(line_number: usize, error: std::io::Error) -> Errors {
+        Errors::IoError(std::io::Error::new(error.kind(), ReadError { line_number, error }))
+    }
+
+    //the line of the file the error is about, if there is one
+    pub fn line_number(&self) -> Option<usize> {
+        match self {
+            Errors::LineParseError { line_number } => Some(*line_number),
+            Errors::IoError(e) => e.get_ref()?.downcast_ref::<ReadError>().map(|e| e.line_number),
+            //the line in the included file
+            Errors::IncludeError { error, .. } => error.line_number(),
+            _ => None,
+        }
+    }
+}
+
+//an I/O error that happened while reading a line of a text file
+#[derive(Debug)]
+pub struct ReadError {
+    pub line_number: usize,
+    pub error: std::io::Error,
+}
+
+impl std::fmt::Display for ReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line_number, self.error)
+    }
+}
+
+impl std::error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
 }
  
-#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Direction {
     North,
     South,
     East,
     West,
 }
+
+//every direction, in the order rooms list their exits
+pub const ALL_DIRECTIONS: [Direction; 4] = [Direction::North, Direction::South, Direction::East, Direction::West];
+
+impl std::fmt::Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Direction::North => "North",
+            Direction::South => "South",
+            Direction::East => "East",
+            Direction::West => "West",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl std::str::FromStr for Direction {
+    type Err = Errors;
+
+    //the name or its first letter, in any case
+    fn from_str(dir: &str) -> Result<Self, Errors> {
+        match dir.trim().to_lowercase().as_str() {
+            "north" | "n" => Ok(Direction::North),
+            "south" | "s" => Ok(Direction::South),
+            "east" | "e" => Ok(Direction::East),
+            "west" | "w" => Ok(Direction::West),
+            _ => Err(Errors::DirectionParseError(dir.to_string())),
+        }
+    }
+}
+
+impl Direction {
+    pub fn opposite(self) -> Direction {
+        match self {
+            Direction::North => Direction::South,
+            Direction::South => Direction::North,
+            Direction::East => Direction::West,
+            Direction::West => Direction::East,
+        }
+    }
+}
  
+//a compass direction or a name like "trapdoor"
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ExitKind {
+    Compass(Direction),
+    Named(String),
+}
+
+impl std::fmt::Display for ExitKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExitKind::Compass(dir) => write!(f, "{}", dir),
+            ExitKind::Named(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exit {
+    pub kind: ExitKind,
+    pub to: String,
+    //secret passages, `find_path` only uses them when asked to
+    pub hidden: bool,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone)]
 pub struct Neighbours{
-    pub west: String,
-    pub east: String,
-    pub south: String,
-    pub north: String,
+    //north, south, east, west
+    compass: [Option<Exit>; 4],
+    //in the order they were added
+    named: Vec<Exit>,
+}
+
+fn compass_index(direction: Direction) -> usize {
+    match direction {
+        Direction::North => 0,
+        Direction::South => 1,
+        Direction::East => 2,
+        Direction::West => 3,
+    }
 }
  
 impl Neighbours {
     pub fn clone(&self) -> Neighbours{
         Neighbours{
-            west: self.west.clone(),
-            east: self.east.clone(),
-            north: self.north.clone(),
-            south: self.south.clone(),
+            compass: self.compass.clone(),
+            named: self.named.clone(),
         }
     }
  
     pub fn new() -> Self {
         Neighbours{
-            north: String::from(""),
-            west: String::from(""),
-            east: String::from(""),
-            south: String::from("")
+            compass: [None, None, None, None],
+            named: Vec::new(),
+        }
+    }
+
+    //replaces the exit of the same kind, an empty room name removes it
+    pub fn set(&mut self, kind: ExitKind, room_name: String, hidden: bool) {
+        if room_name.is_empty() {
+            self.remove(&kind);
+            return;
+        }
+        let exit = Exit { kind, to: room_name, hidden };
+        if let ExitKind::Compass(dir) = exit.kind {
+            self.compass[compass_index(dir)] = Some(exit);
+        } else if let Some(old) = self.named.iter_mut().find(|e| e.kind == exit.kind) {
+            *old = exit;
+        } else {
+            self.named.push(exit);
         }
     }
+
+    pub fn get(&self, kind: &ExitKind) -> Option<&Exit> {
+        match kind {
+            ExitKind::Compass(dir) => self.compass[compass_index(*dir)].as_ref(),
+            ExitKind::Named(_) => self.named.iter().find(|e| e.kind == *kind),
+        }
+    }
+
+    pub fn get_mut(&mut self, kind: &ExitKind) -> Option<&mut Exit> {
+        match kind {
+            ExitKind::Compass(dir) => self.compass[compass_index(*dir)].as_mut(),
+            ExitKind::Named(_) => self.named.iter_mut().find(|e| e.kind == *kind),
+        }
+    }
+
+    pub fn remove(&mut self, kind: &ExitKind) -> Option<Exit> {
+        match kind {
+            ExitKind::Compass(dir) => self.compass[compass_index(*dir)].take(),
+            ExitKind::Named(_) => {
+                let index = self.named.iter().position(|e| e.kind == *kind)?;
+                Some(self.named.remove(index))
+            }
+        }
+    }
+
+    //compass exits first (north, south, east, west), then the named ones in the order they were added
+    pub fn iter(&self) -> impl Iterator<Item = &Exit> {
+        self.compass.iter().flatten().chain(self.named.iter())
+    }
+
+    fn get_compass(&self, direction: Direction) -> Option<String> {
+        self.get(&ExitKind::Compass(direction)).map(|exit| exit.to.clone())
+    }
+
     //setters
     pub fn set_west(&mut self, room_name: String) {
-        self.west = room_name;
+        self.set(ExitKind::Compass(Direction::West), room_name, false);
     }
  
     pub fn set_east(&mut self, room_name: String) {
-        self.east = room_name;
+        self.set(ExitKind::Compass(Direction::East), room_name, false);
     }
  
     pub fn set_north(&mut self, room_name: String) {
-        self.north = room_name;
+        self.set(ExitKind::Compass(Direction::North), room_name, false);
     }
  
     pub fn set_south(&mut self, room_name: String) {
-        self.south = room_name;
+        self.set(ExitKind::Compass(Direction::South), room_name, false);
     }
  
     //getters
     pub fn get_west(self) -> Option<String> {
-        if self.west == ""{
-            return None;
-        }
-        Some(self.west.clone())
+        self.get_compass(Direction::West)
     }
  
     pub fn get_east(self) -> Option<String>{
-        if self.east == ""{
-            return None;
-        }
-        Some(self.east.clone())
+        self.get_compass(Direction::East)
     }
  
     pub fn get_north(self) -> Option<String>{
-        if self.north == ""{
-            return None;
-        }
-        Some(self.north.clone())
+        self.get_compass(Direction::North)
     }
  
     pub fn get_south(self) -> Option<String>{
-        if self.south == ""{
-            return None;
-        }
-        Some(self.south.clone())
+        self.get_compass(Direction::South)
     }
 }
  
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone)]
 pub struct Room {
     pub name: String,
-    pub next_to: Neighbours
+    pub next_to: Neighbours,
+    //other names the room can be found by, see `names`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub aliases: Vec<String>,
 }
  
 impl Room {
     pub fn new(name: String) -> Self {
         Room{
             name,
-            next_to: Neighbours::new()
+            next_to: Neighbours::new(),
+            aliases: Vec::new(),
         }
     }
 }
  
+#[derive(Clone)]
 pub struct Dungeon {
-    rooms: HashMap<String, Room>
+    rooms: HashMap<String, Room>,
+    //room names in the order they were added, everything that goes through all the rooms uses it
+    order: Vec<String>,
+    //doors that are locked when a session starts, stored for both sides of the link
+    locked: HashSet<(String, Direction)>,
+    triggers: Vec<triggers::Trigger>,
+    journal: journal::Journal,
+    observers: events::Observers,
+    adjacency: adjacency::Adjacency,
 }
  
 impl Dungeon {
     pub fn new() -> Self {
         Dungeon{
-            rooms: HashMap::<String, Room>::new()
+            rooms: HashMap::<String, Room>::new(),
+            order: Vec::new(),
+            locked: HashSet::new(),
+            triggers: Vec::new(),
+            journal: journal::Journal::default(),
+            observers: events::Observers::default(),
+            adjacency: adjacency::Adjacency::default(),
         }    
     }
  
     pub fn add_room(&mut self, name: &str) -> Result<(), Errors> {
-        if self.rooms.contains_key(&name.to_string()) {
+        if self.rooms.contains_key(&name.to_string()) || self.resolve_room_name(name).is_some() {
             Err(Errors::DuplicateRoom(name.to_string()))
         }
         else{
-            self.rooms.insert(name.to_string(), Room::new(name.to_string()));
-            Ok(())
+            self.edit(|dungeon| {
+                dungeon.touch_room(name);
+                dungeon.rooms.insert(name.to_string(), Room::new(name.to_string()));
+                dungeon.order_mut().push(name.to_string());
+                dungeon.sync_room(name);
+                dungeon.emit(|| DungeonEvent::RoomAdded { room: name.to_string() });
+                Ok(())
+            })
         }
     }
  
+    //also removes every exit that leads to the room, the locks on them and the triggers that
+    //mention the room
+    pub fn remove_room(&mut self, room_name: &str) -> Result<Room, Errors> {
+        self.exact_room(room_name)?;
+        self.edit(|dungeon| {
+            dungeon.touch_room(room_name);
+            let room = dungeon.rooms.remove(room_name).unwrap();
+            dungeon.order_mut().retain(|name| name != room_name);
+            dungeon.sync_room(room_name);
+            let exits: Vec<(String, ExitKind)> = dungeon
+                .rooms()
+                .flat_map(|other| other.next_to.iter().filter(|e| e.to == room_name).map(|e| (other.name.clone(), e.kind.clone())))
+                .collect();
+            for (other, kind) in exits {
+                dungeon.remove_exit(&other, &kind);
+                if let ExitKind::Compass(dir) = kind {
+                    dungeon.set_locked(&other, dir, false);
+                }
+            }
+            let locks: Vec<Direction> = dungeon.locked.iter().filter(|(name, _)| name == room_name).map(|(_, dir)| *dir).collect();
+            for dir in locks {
+                dungeon.set_locked(room_name, dir, false);
+            }
+            if dungeon.triggers.iter().any(|trigger| trigger.rooms().contains(&room_name)) {
+                dungeon.triggers_mut().retain(|trigger| !trigger.rooms().contains(&room_name));
+            }
+            dungeon.emit(|| DungeonEvent::RoomRemoved { room: room_name.to_string() });
+            Ok(room)
+        })
+    }
+ 
     pub fn get_room(&self, room_name: &str) -> Result<&Room, Errors> {
+        let room_name = self.resolve_room_name(room_name).unwrap_or(room_name);
         if self.rooms.contains_key(&room_name.to_string()) {
             Ok(self.rooms.get(&room_name.to_string()).unwrap())
         }
@@ -141,48 +425,91 @@ impl Dungeon {
         direction: Direction,
         other_room_name: &str,
     ) -> Result<(), Errors> {
-        if self.rooms.contains_key(&room_name.to_string()){
-            if self.rooms.contains_key(&other_room_name.to_string()){
-                let mut current = self.rooms.get_mut(&room_name.to_string()).unwrap();
-                //let mut second = self.rooms.get_mut(&other_room_name.to_string()).unwrap();;
-                match direction{
-                    Direction::East => {
-                        current.next_to.set_east(other_room_name.to_string());
-                        //change the current room to the second one
-                        current = self.rooms.get_mut(&other_room_name.to_string()).unwrap();
-                        current.next_to.set_west(room_name.to_string());
-                    }
-                    Direction::West => {
-                        current.next_to.set_west(other_room_name.to_string());
-                        //change the current room to the second one 
-                        current = self.rooms.get_mut(&other_room_name.to_string()).unwrap();
-                        current.next_to.set_east(room_name.to_string());
-                    }
-                    Direction::South => {
-                        current.next_to.set_south(other_room_name.to_string());
-                        //change the current room to the second one 
-                        current = self.rooms.get_mut(&other_room_name.to_string()).unwrap();
-                        current.next_to.set_north(room_name.to_string());
-                    }
-                    Direction::North => {
-                        current.next_to.set_north(other_room_name.to_string());
-                        //change the current room to the second one
-                        current = self.rooms.get_mut(&other_room_name.to_string()).unwrap();
-                        current.next_to.set_south(other_room_name.to_string());
+        self.edit(|dungeon| dungeon.link(room_name, direction, other_room_name, false))
+    }
+
+    fn link(
+        &mut self,
+        room_name: &str,
+        direction: Direction,
+        other_room_name: &str,
+        hidden: bool,
+    ) -> Result<(), Errors> {
+        self.exact_room(room_name)?;
+        self.exact_room(other_room_name)?;
+        self.set_exit(room_name, ExitKind::Compass(direction), other_room_name, hidden);
+        self.set_exit(other_room_name, ExitKind::Compass(direction.opposite()), room_name, hidden);
+        //a new link is always an open one
+        self.set_locked(room_name, direction, false);
+        self.set_locked(other_room_name, direction.opposite(), false);
+        Ok(())
+    }
+
+    //exits that lead to the room and the locks and triggers of the room follow the new name
+    pub fn rename_room(&mut self, room_name: &str, new_name: &str) -> Result<(), Errors> {
+        self.exact_room(room_name)?;
+        if self.rooms.contains_key(new_name) || self.resolve_room_name(new_name).is_some_and(|name| name != room_name) {
+            return Err(Errors::DuplicateRoom(new_name.to_string()));
+        }
+        self.edit(|dungeon| {
+            dungeon.touch_room(room_name);
+            dungeon.touch_room(new_name);
+            let mut room = dungeon.rooms.remove(room_name).unwrap();
+            room.name = new_name.to_string();
+            dungeon.rooms.insert(new_name.to_string(), room);
+            dungeon.adjacency.rename(room_name, new_name);
+            for name in dungeon.order_mut().iter_mut().filter(|name| *name == room_name) {
+                *name = new_name.to_string();
+            }
+
+            let exits: Vec<(String, ExitKind)> = dungeon
+                .rooms()
+                .flat_map(|other| other.next_to.iter().filter(|e| e.to == room_name).map(|e| (other.name.clone(), e.kind.clone())))
+                .collect();
+            for (other, kind) in exits {
+                dungeon.room_mut(&other).unwrap().next_to.get_mut(&kind).unwrap().to = new_name.to_string();
+            }
+            let locks: Vec<Direction> = dungeon.locked.iter().filter(|(name, _)| name == room_name).map(|(_, dir)| *dir).collect();
+            for dir in locks {
+                dungeon.set_locked(room_name, dir, false);
+                dungeon.set_locked(new_name, dir, true);
+            }
+            if dungeon.triggers.iter().any(|trigger| trigger.rooms().contains(&room_name)) {
+                for trigger in dungeon.triggers_mut() {
+                    for name in trigger.rooms_mut().into_iter().filter(|name| *name == room_name) {
+                        *name = new_name.to_string();
                     }
                 }
             }
-            else{
-                return Err(Errors::UnknownRoom(other_room_name.to_string()));
-            }
-        }
-        else{
-            return Err(Errors::UnknownRoom(room_name.to_string()));
-        }
-        Ok(())
+            dungeon.emit(|| DungeonEvent::RoomRenamed { from: room_name.to_string(), to: new_name.to_string() });
+            Ok(())
+        })
+    }
+
+    //every exit is set and removed through these two, the room has to exist
+    pub(crate) fn set_exit(&mut self, room_name: &str, kind: ExitKind, other_room_name: &str, hidden: bool) {
+        let neighbours = &mut self.room_mut(room_name).unwrap().next_to;
+        let previous = neighbours.get(&kind).map(|exit| exit.to.clone());
+        neighbours.set(kind.clone(), other_room_name.to_string(), hidden);
+        self.sync_room(room_name);
+        self.emit(|| DungeonEvent::LinkSet {
+            room: room_name.to_string(),
+            kind,
+            to: other_room_name.to_string(),
+            hidden,
+            previous,
+        });
+    }
+
+    pub(crate) fn remove_exit(&mut self, room_name: &str, kind: &ExitKind) -> Option<Exit> {
+        let exit = self.room_mut(room_name).unwrap().next_to.remove(kind)?;
+        self.sync_room(room_name);
+        self.emit(|| DungeonEvent::LinkRemoved { room: room_name.to_string(), kind: kind.clone(), to: exit.to.clone() });
+        Some(exit)
     }
  
     pub fn get_next_room(&self, room_name: &str, direction: Direction) -> Result<Option<&Room>, Errors> {
+        let room_name = self.resolve_room_name(room_name).unwrap_or(room_name);
         if self.rooms.contains_key(&room_name.to_string()) {
             let get_room = self.rooms.get(&room_name.to_string());
             match direction{
@@ -231,188 +558,214 @@ impl Dungeon {
     }
 }
  
-use std::io::BufRead;
-//helper functions
-fn str_to_direction(dir: &str) -> Direction{
-    match dir {
-        "North" => Direction::North,
-        "East" => Direction::East,
-        "South" => Direction::South,
-        "West" => Direction::West,
-        _ => unreachable!()
+//named exits and secret passages
+impl Dungeon {
+    //named exits only go one way, add another one for the way back
+    pub fn set_named_exit(
+        &mut self,
+        room_name: &str,
+        exit_name: &str,
+        other_room_name: &str,
+        hidden: bool,
+    ) -> Result<(), Errors> {
+        self.exact_room(other_room_name)?;
+        self.exact_room(room_name)?;
+        self.edit(|dungeon| {
+            dungeon.set_exit(room_name, ExitKind::Named(exit_name.to_string()), other_room_name, hidden);
+            Ok(())
+        })
+    }
+
+    pub fn get_named_exit(&self, room_name: &str, exit_name: &str) -> Result<Option<&Room>, Errors> {
+        let exit = self.get_room(room_name)?.next_to.get(&ExitKind::Named(exit_name.to_string()));
+        Ok(exit.and_then(|exit| self.rooms.get(&exit.to)))
+    }
+
+    //`set_link` with both sides of the link hidden
+    pub fn set_hidden_link(
+        &mut self,
+        room_name: &str,
+        direction: Direction,
+        other_room_name: &str,
+    ) -> Result<(), Errors> {
+        self.edit(|dungeon| dungeon.link(room_name, direction, other_room_name, true))
     }
 }
  
+//looking through the dungeon, rooms come in the order they were added and the exits of a room
+//in the order of `Neighbours::iter`
 impl Dungeon {
- 
-    pub fn from_reader<B: BufRead>(reader: B) -> Result<Self, Errors> {
-        //if we have an empty reader
-        let mut new_dungeon = Dungeon::new();
- 
-        let mut row_counter = 0;
-        //we have a vector of all the lines and we have checked it for any io::errors
-        let lines_vec = reader.lines()
-                                  .map(|l| {
-                                      if let Err(val) = l{
-                                          return Err(Errors::IoError(val));
-                                      }
-                                      return Ok(l.unwrap());
-                                    })
-                                   .map(|l| l.unwrap()).collect::<Vec<String>>();
-        //cheking for empty buffer
-        if lines_vec.len() == 0 {
-            return Err(Errors::LineParseError{ line_number: row_counter });
+    pub fn rooms(&self) -> impl Iterator<Item = &Room> {
+        self.order.iter().map(|name| &self.rooms[name])
+    }
+
+    pub fn room_names(&self) -> impl Iterator<Item = &str> {
+        self.rooms().map(|room| room.name.as_str())
+    }
+
+    //every compass link of every room, a two-way link shows up once from each side
+    pub fn links(&self) -> impl Iterator<Item = (&str, Direction, &str)> {
+        self.rooms().flat_map(|room| {
+            room.next_to.iter().filter_map(move |exit| match exit.kind {
+                ExitKind::Compass(dir) => Some((room.name.as_str(), dir, exit.to.as_str())),
+                ExitKind::Named(_) => None,
+            })
+        })
+    }
+
+    //the rooms behind the compass links of a room
+    pub fn neighbours(&self, room_name: &str) -> Result<impl Iterator<Item = (Direction, &Room)>, Errors> {
+        let room = self.get_room(room_name)?;
+        Ok(room.next_to.iter().filter_map(move |exit| match exit.kind {
+            ExitKind::Compass(dir) => self.rooms.get(&exit.to).map(|next| (dir, next)),
+            ExitKind::Named(_) => None,
+        }))
+    }
+
+    //every exit of a room, named and hidden ones included
+    pub fn exits(&self, room_name: &str) -> Result<impl Iterator<Item = &Exit>, Errors> {
+        Ok(self.get_room(room_name)?.next_to.iter())
+    }
+}
+
+//copying another dungeon into this one
+impl Dungeon {
+    //every room of `other` is added under the name `rename` gives it, with its exits, locks and
+    //triggers. Exits are copied one side at a time, so one-way links stay one-way. With `union`
+    //a room that is already here is shared, its exits from `other` replace the ones here
+    pub(crate) fn absorb(
+        &mut self,
+        other: &Dungeon,
+        rename: impl Fn(&str) -> String,
+        union: bool,
+    ) -> Result<(), Errors> {
+        self.edit(|dungeon| dungeon.absorb_all(other, rename, union))
+    }
+
+    fn absorb_all(&mut self, other: &Dungeon, rename: impl Fn(&str) -> String, union: bool) -> Result<(), Errors> {
+        for name in other.room_names() {
+            let name = rename(name);
+            if !(union && self.rooms.contains_key(&name)) {
+                self.add_room(&name)?;
+            }
         }
-        row_counter += 1;
-
-        let mut reached_nl = false;
-        let mut row_reached = 0;
-        for mut line in lines_vec { 
-            line = line.trim().to_string();
-            if !reached_nl{
-                if row_counter == 1{
-                    if line != "## Rooms"{
-                        return Err(Errors::LineParseError{ line_number: row_counter });
-                    }
-                    else {
-                        row_counter += 1;
-                        continue;
-                    }
-                }
-                else {
-                    if line.as_bytes().len() == 0 {
-                        //we have reached a newline
-                        reached_nl = true;
-                        row_reached = row_counter;
-                    }
-                    else{
-                        //geting and the first letter and checking for the correct format wihich is /- <name>/
-                        if line.as_bytes()[0] as char != '-'{
-                            return Err(Errors::LineParseError{ line_number: row_counter });
-                        }
-                        else {
-                            new_dungeon.add_room(&line[2..])?;
-                        }
-                        row_counter += 1;
-                    }
-                }
+        for room in other.rooms() {
+            for exit in room.next_to.iter() {
+                self.set_exit(&rename(&room.name), exit.kind.clone(), &rename(&exit.to), exit.hidden);
             }
-            else{
-                if row_counter == row_reached {
-                    if line != "## Links"{
-                        return Err(Errors::LineParseError{ line_number: row_counter });
-                    }
-                    else{
-                        row_counter += 1;
-                        continue;
-                    }
-                }
-                //geting and the first letter and checking for the correct format wihich is /- <name>/
-                else if line.as_bytes()[0] as char != '-' {
-                    return Err(Errors::LineParseError{ line_number: row_counter });
-                }
-                else {
-                    row_counter += 1;
-                    let words: Vec<&str> = line[2..].split(" -> ").collect();
-                    if words.len() != 3 {
-                        
-                        return Err(Errors::LineParseError{ line_number: row_counter });
-                    }
- 
-                    if words[1].trim() != "West" && words[1].trim() != "East" && words[1].trim() != "North" && words[1].trim() != "South" {
-                        return Err(Errors::DirectionParseError(words[1].to_string()));
-                    }   
- 
-                    let dir = str_to_direction(words[1]);
- 
-                    new_dungeon.set_link(words[0], dir, words[2])?;
+        }
+        for room in other.rooms() {
+            let name = rename(&room.name);
+            for alias in room.aliases.iter().map(|alias| rename(alias)) {
+                //a union keeps the aliases both rooms have once
+                if !(union && self.rooms[&name].aliases.contains(&alias)) {
+                    self.add_alias(&name, &alias)?;
                 }
             }
         }
-        return Ok(new_dungeon);
+        for (room, dir) in &other.locked {
+            self.set_locked(&rename(room), *dir, true);
+        }
+        for trigger in &other.triggers {
+            let mut trigger = trigger.clone();
+            for room in trigger.rooms_mut() {
+                *room = rename(room);
+            }
+            if !(union && self.triggers.contains(&trigger)) {
+                self.triggers_mut().push(trigger);
+            }
+        }
+        Ok(())
     }
 }
+ 
+//locked doors, every session starts with its own copy of them
+impl Dungeon {
+    //locking a direction without a link does nothing
+    pub fn lock_link(&mut self, room_name: &str, direction: Direction) -> Result<(), Errors> {
+        self.exact_room(room_name)?;
+        let other_room_name = match self.get_next_room(room_name, direction)? {
+            Some(room) => room.name.clone(),
+            None => return Ok(()),
+        };
+        self.edit(|dungeon| {
+            dungeon.set_locked(room_name, direction, true);
+            dungeon.set_locked(&other_room_name, direction.opposite(), true);
+            Ok(())
+        })
+    }
 
-fn is_not_visited(target: String, path: Vec<String>) -> bool{
-    for x in path{
-        if x == target{
-            return false;
-        }
-    }   
-    return true;
+    pub fn unlock_link(&mut self, room_name: &str, direction: Direction) -> Result<(), Errors> {
+        self.exact_room(room_name)?;
+        let other_room_name = match self.get_next_room(room_name, direction)? {
+            Some(room) => room.name.clone(),
+            None => return Ok(()),
+        };
+        self.edit(|dungeon| {
+            dungeon.set_locked(room_name, direction, false);
+            dungeon.set_locked(&other_room_name, direction.opposite(), false);
+            Ok(())
+        })
+    }
+
+    pub fn is_locked(&self, room_name: &str, direction: Direction) -> bool {
+        self.locked.contains(&(room_name.to_string(), direction))
+    }
 }
+
+use std::io::BufRead;
  
-fn findpaths<'a>(g: &'a HashMap<String, Vec<String>>, src: &'a str, dst: &'a str) -> Vec<String>{
-    let mut q = Vec::<Vec::<String>>::new();
- 
-    // path vector to store the current path
-    let mut path = Vec::<String>::new();
-    path.push(src.to_string());
-    q.insert(0, path);
-    while !q.is_empty() {
-        path = q[q.len() - 1].clone();
-        q.pop();
-        let last: String = path[path.len() - 1].clone();
+impl Dungeon {
  
-        //we have reached the end
-        if last == dst{
-            return path;
-        }
-                
-        for name in g[&last].clone() {
-            if is_not_visited(name.to_string(), path.clone()) {
-                let mut newpath = Vec::<String>::new();
-                for road in path.clone(){
-                    newpath.push(road);
-                }
-                newpath.push(name.to_string());
-                q.push(newpath);
+    //see parser.rs for the format
+    pub fn from_reader<B: BufRead>(mut reader: B) -> Result<Self, Errors> {
+        let mut parser = parser::Parser::new();
+        //one buffer for every line, so a file of any size is read in the same memory
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match reader.read_line(&mut line) {
+                Ok(0) => break,
+                //includes need a resolver, see `Dungeon::from_resolver`
+                Ok(_) => if parser.line(&line)?.is_some() {
+                    return Err(Errors::LineParseError { line_number: parser.line_number() });
+                },
+                Err(error) => return Err(parser.read_error(error)),
             }
         }
+        parser.finish()
     }
-    return vec![];
 }
 
 //finding path algorythm part
 impl Dungeon {
 
+    //hidden exits are left out, see `find_path_with_hidden`
     pub fn find_path(
         &self,
         start_room_name: &str,
         end_room_name: &str
     ) -> Result<Option<Vec<&Room>>, Errors> {
+        self.find_path_through(start_room_name, end_room_name, false)
+    }
 
-        let mut graph = HashMap::<String, Vec<String>>::new();
-        for n in &self.rooms.clone(){
-            let mut current_neigbours = Vec::<String>::new();
-            //n.1 - is a Room
-            if n.1.next_to.clone().get_west() != None{
-                current_neigbours.push(n.1.next_to.clone().get_west().unwrap());
-            }
-            if n.1.next_to.clone().get_east() != None{
-                current_neigbours.push(n.1.next_to.clone().get_east().unwrap());
-            }
-            if n.1.next_to.clone().get_north() != None{
-                current_neigbours.push(n.1.next_to.clone().get_north().unwrap());
-            }
-            if n.1.next_to.clone().get_south() != None{
-                current_neigbours.push(n.1.next_to.clone().get_south().unwrap());
-            }
-            graph.insert(n.0.to_string(), current_neigbours);
-        }
-        let path_in_str_vec = findpaths(&graph, start_room_name, end_room_name);
-
-        let mut result = Vec::<&Room>::new();
-        for room in path_in_str_vec{
-            result.push(self.rooms.get(&room).unwrap());
-        }
+    pub fn find_path_with_hidden(
+        &self,
+        start_room_name: &str,
+        end_room_name: &str
+    ) -> Result<Option<Vec<&Room>>, Errors> {
+        self.find_path_through(start_room_name, end_room_name, true)
+    }
 
-        //in case we have nothing in the path
-        if result.len() == 0 {
-            return Ok(None);
-        }
+    fn find_path_through(
+        &self,
+        start_room_name: &str,
+        end_room_name: &str,
+        use_hidden: bool
+    ) -> Result<Option<Vec<&Room>>, Errors> {
 
-        Ok(Some(result))
+        let path = self.room_path(start_room_name, end_room_name, use_hidden)?;
+        Ok(path.map(|path| path.into_iter().map(|name| &self.rooms[name]).collect()))
     }
 }
 
@@ -445,17 +798,9 @@ macro_rules! timeout {
     }
 }
 
-const ALL_DIRECTIONS: [Direction; 4] = [
-    Direction::North,
-    Direction::South,
-    Direction::East,
-    Direction::West
-];
-
 fn all_links<'a>(dungeon: &'a Dungeon, room: &'a Room) -> Vec<&'a str> {
-    ALL_DIRECTIONS.iter().
-        flat_map(|dir| dungeon.get_next_room(&room.name, *dir).unwrap()).
-        map(|r| r.name.as_str()).
+    dungeon.neighbours(&room.name).unwrap().
+        map(|(_, r)| r.name.as_str()).
         collect::<Vec<&'a str>>()
 }
 
@@ -582,6 +927,23 @@ fn test_overwriting_a_room_link() {
     );
 }
 
+#[test]
+fn test_links_lead_back() {
+    let mut dungeon = Dungeon::new();
+    dungeon.add_room("Hall").unwrap();
+    dungeon.add_room("Tower").unwrap();
+
+    for (direction, back) in [
+        (Direction::North, Direction::South),
+        (Direction::South, Direction::North),
+        (Direction::East, Direction::West),
+        (Direction::West, Direction::East),
+    ] {
+        dungeon.set_link("Hall", direction, "Tower").unwrap();
+        assert_eq!(dungeon.get_next_room("Tower", back).unwrap().unwrap().name, "Hall");
+    }
+}
+
 #[test]
 fn test_cyrillic_room_names() {
     let mut dungeon = Dungeon::new();
@@ -615,6 +977,48 @@ fn test_io_error() {
     });
 }
 
+//gives its text and then fails
+#[cfg(test)]
+struct FailingAfter {
+    text: &'static [u8],
+}
+
+#[cfg(test)]
+impl Read for FailingAfter {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.text.is_empty() {
+            return Err(io::Error::other("disk on fire"));
+        }
+        let length = self.text.len().min(buf.len());
+        buf[..length].copy_from_slice(&self.text[..length]);
+        self.text = &self.text[length..];
+        Ok(length)
+    }
+}
+
+#[test]
+fn test_streaming_parser() {
+    //a big generated file
+    let rooms = (0..20_000).map(|i| format!("- Room {}\n", i));
+    let links = (1..20_000).map(|i| format!("- Room {} -> East -> Room {}\n", i - 1, i));
+    let text = std::iter::once(String::from("## Rooms\n"))
+        .chain(rooms)
+        .chain(std::iter::once(String::from("## Links\n")))
+        .chain(links);
+    let reader = io::BufReader::new(io::Cursor::new(text.collect::<String>()));
+    let dungeon = Dungeon::from_reader(reader).unwrap();
+    assert_eq!(dungeon.get_next_room("Room 19998", Direction::East).unwrap().unwrap().name, "Room 19999");
+
+    let reader = io::BufReader::new(FailingAfter { text: b"## Rooms\n- Room\n## Links\n" });
+    let error = Dungeon::from_reader(reader).err().unwrap();
+    assert!(matches!(error, Errors::IoError(_)));
+    assert_eq!(error.line_number(), Some(4));
+    assert_eq!(error.to_string(), "read error: line 4: disk on fire");
+
+    //a missing links section is reported after the last line
+    assert_eq!(Dungeon::from_reader("## Rooms\n- A\n".as_bytes()).err().unwrap().line_number(), Some(3));
+}
+
 const TEST_INPUT_1: &str = "
 ## Rooms
 - Entrance
@@ -686,7 +1090,7 @@ const TEST_INPUT_7: &str = "
 fn test_invalid_parsing() {
     assert!(matches!(Dungeon::from_reader("".as_bytes()), Err(Errors::LineParseError { line_number: 0 })));
     assert!(matches!(Dungeon::from_reader(TEST_INPUT_4.trim().as_bytes()), Err(Errors::LineParseError { line_number: 1 })));
-    assert!(matches!(Dungeon::from_reader(TEST_INPUT_5.trim().as_bytes()), Err(Errors::LineParseError { line_number: 3 })));
+    assert!(matches!(Dungeon::from_reader(TEST_INPUT_5.trim().as_bytes()), Err(Errors::LineParseError { line_number: 2 })));
     assert!(matches!(Dungeon::from_reader(TEST_INPUT_6.trim().as_bytes()), Err(Errors::UnknownRoom(_))));
     assert!(matches!(Dungeon::from_reader(TEST_INPUT_7.trim().as_bytes()), Err(Errors::DirectionParseError(_))));
 }
@@ -796,4 +1200,64 @@ fn test_finding_no_path() {
         let path = dungeon.find_path("Mystery Room", "Treasure Room");
         assert!(path.is_err());
     });
-}
\ No newline at end of file
+}
+#[test]
+fn test_named_and_hidden_exits() {
+    let mut dungeon = Dungeon::new();
+    dungeon.add_room("Hallway").unwrap();
+    dungeon.add_room("Library").unwrap();
+    dungeon.add_room("Cellar").unwrap();
+    dungeon.set_link("Hallway", Direction::East, "Library").unwrap();
+    dungeon.set_named_exit("Cellar", "trapdoor", "Hallway", false).unwrap();
+    dungeon.set_named_exit("Hallway", "mirror", "Library", true).unwrap();
+    dungeon.set_hidden_link("Library", Direction::South, "Cellar").unwrap();
+
+    let hallway = dungeon.get_room("Hallway").unwrap();
+    assert_eq!(hallway.next_to.clone().get_east(), Some(String::from("Library")));
+    assert_eq!(
+        hallway.next_to.iter().map(|e| e.kind.to_string()).collect::<Vec<_>>(),
+        ["East", "mirror"]
+    );
+    assert_eq!(dungeon.get_named_exit("Cellar", "trapdoor").unwrap().unwrap().name, "Hallway");
+    assert!(dungeon.get_named_exit("Hallway", "trapdoor").unwrap().is_none());
+    assert!(matches!(dungeon.set_named_exit("Hallway", "door", "Attic", false), Err(Errors::UnknownRoom(_))));
+
+    //the only way from the library to the cellar is a secret one
+    assert!(dungeon.find_path("Library", "Cellar").unwrap().is_none());
+    let path = dungeon.find_path_with_hidden("Library", "Cellar").unwrap().unwrap();
+    assert_eq!(path.iter().map(|r| r.name.as_str()).collect::<Vec<_>>(), ["Library", "Cellar"]);
+    assert_eq!(dungeon.get_next_room("Cellar", Direction::North).unwrap().unwrap().name, "Library");
+}
+
+#[test]
+fn test_iterating_over_the_dungeon() {
+    let mut dungeon = Dungeon::new();
+    dungeon.add_room("Hallway").unwrap();
+    dungeon.add_room("Entrance").unwrap();
+    dungeon.add_room("Cellar").unwrap();
+    dungeon.set_link("Entrance", Direction::East, "Hallway").unwrap();
+    dungeon.set_link("Hallway", Direction::North, "Cellar").unwrap();
+    dungeon.set_named_exit("Cellar", "trapdoor", "Entrance", false).unwrap();
+
+    assert_eq!(dungeon.room_names().collect::<Vec<_>>(), ["Hallway", "Entrance", "Cellar"]);
+    assert_eq!(dungeon.rooms().count(), 3);
+    assert_eq!(
+        dungeon.links().collect::<Vec<_>>(),
+        [
+            ("Hallway", Direction::North, "Cellar"),
+            ("Hallway", Direction::West, "Entrance"),
+            ("Entrance", Direction::East, "Hallway"),
+            ("Cellar", Direction::South, "Hallway"),
+        ]
+    );
+    assert_eq!(
+        dungeon.neighbours("Hallway").unwrap().map(|(dir, r)| (dir, r.name.as_str())).collect::<Vec<_>>(),
+        [(Direction::North, "Cellar"), (Direction::West, "Entrance")]
+    );
+    assert_eq!(dungeon.neighbours("Cellar").unwrap().count(), 1);
+    assert_eq!(
+        dungeon.exits("Cellar").unwrap().map(|e| e.kind.to_string()).collect::<Vec<_>>(),
+        ["South", "trapdoor"]
+    );
+    assert!(matches!(dungeon.exits("Attic"), Err(Errors::UnknownRoom(_))));
+}
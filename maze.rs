@@ -0,0 +1,413 @@
+use crate::{Direction, Dungeon, Errors};
+
+// maze generation over a rectangular grid
+// every cell becomes a room named by its coordinates (see `cell_name`), row 0 is the
+// northernmost row and column 0 the westernmost one
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MazeAlgorithm {
+    RecursiveBacktracker,
+    Prim,
+    Kruskal,
+    Wilson,
+    Eller,
+}
+
+pub fn cell_name(x: usize, y: usize) -> String {
+    format!("{},{}", x, y)
+}
+
+// `braid` is the probability (0.0 - 1.0) for every dead end to be removed by opening an
+// extra wall, 0.0 gives a perfect maze and 1.0 a maze without dead ends
+pub fn generate_maze(
+    width: usize,
+    height: usize,
+    algorithm: MazeAlgorithm,
+    braid: f64,
+    seed: u64,
+) -> Result<Dungeon, Errors> {
+    let mut grid = Grid::new(width, height);
+    let mut rng = Rng::new(seed);
+
+    if grid.len() > 0 {
+        match algorithm {
+            MazeAlgorithm::RecursiveBacktracker => recursive_backtracker(&mut grid, &mut rng),
+            MazeAlgorithm::Prim => prim(&mut grid, &mut rng),
+            MazeAlgorithm::Kruskal => kruskal(&mut grid, &mut rng),
+            MazeAlgorithm::Wilson => wilson(&mut grid, &mut rng),
+            MazeAlgorithm::Eller => eller(&mut grid, &mut rng),
+        }
+        remove_dead_ends(&mut grid, &mut rng, braid);
+    }
+
+    grid.to_dungeon()
+}
+
+//small xorshift generator, so the same seed always gives the same maze (or simulation)
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Rng((seed ^ 0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i + 1);
+            items.swap(i, j);
+        }
+    }
+}
+
+struct Grid {
+    width: usize,
+    height: usize,
+    //passage from a cell to its east / south neighbour
+    east: Vec<bool>,
+    south: Vec<bool>,
+}
+
+impl Grid {
+    fn new(width: usize, height: usize) -> Self {
+        Grid {
+            width,
+            height,
+            east: vec![false; width * height],
+            south: vec![false; width * height],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.width * self.height
+    }
+
+    fn neighbours(&self, cell: usize) -> Vec<usize> {
+        let (x, y) = (cell % self.width, cell / self.width);
+        let mut result = Vec::new();
+        if y > 0 {
+            result.push(cell - self.width);
+        }
+        if y + 1 < self.height {
+            result.push(cell + self.width);
+        }
+        if x + 1 < self.width {
+            result.push(cell + 1);
+        }
+        if x > 0 {
+            result.push(cell - 1);
+        }
+        result
+    }
+
+    fn is_open(&self, a: usize, b: usize) -> bool {
+        let (a, b) = if a < b { (a, b) } else { (b, a) };
+        if b == a + 1 && b % self.width != 0 {
+            self.east[a]
+        } else {
+            self.south[a]
+        }
+    }
+
+    fn carve(&mut self, a: usize, b: usize) {
+        let (a, b) = if a < b { (a, b) } else { (b, a) };
+        if b == a + 1 && b % self.width != 0 {
+            self.east[a] = true;
+        } else {
+            self.south[a] = true;
+        }
+    }
+
+    fn degree(&self, cell: usize) -> usize {
+        self.neighbours(cell).into_iter().filter(|&n| self.is_open(cell, n)).count()
+    }
+
+    fn to_dungeon(&self) -> Result<Dungeon, Errors> {
+        let mut dungeon = Dungeon::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                dungeon.add_room(&cell_name(x, y))?;
+            }
+        }
+        for cell in 0..self.len() {
+            let (x, y) = (cell % self.width, cell / self.width);
+            if self.east[cell] {
+                dungeon.set_link(&cell_name(x, y), Direction::East, &cell_name(x + 1, y))?;
+            }
+            if self.south[cell] {
+                dungeon.set_link(&cell_name(x, y), Direction::South, &cell_name(x, y + 1))?;
+            }
+        }
+        Ok(dungeon)
+    }
+}
+
+fn recursive_backtracker(grid: &mut Grid, rng: &mut Rng) {
+    let mut visited = vec![false; grid.len()];
+    let start = rng.below(grid.len());
+    let mut stack = vec![start];
+    visited[start] = true;
+
+    while let Some(&current) = stack.last() {
+        let unvisited: Vec<usize> = grid.neighbours(current).into_iter().filter(|&n| !visited[n]).collect();
+        if unvisited.is_empty() {
+            stack.pop();
+            continue;
+        }
+        let next = unvisited[rng.below(unvisited.len())];
+        grid.carve(current, next);
+        visited[next] = true;
+        stack.push(next);
+    }
+}
+
+fn prim(grid: &mut Grid, rng: &mut Rng) {
+    let mut in_maze = vec![false; grid.len()];
+    let mut in_frontier = vec![false; grid.len()];
+    let mut frontier = Vec::new();
+
+    let start = rng.below(grid.len());
+    in_maze[start] = true;
+    for n in grid.neighbours(start) {
+        in_frontier[n] = true;
+        frontier.push(n);
+    }
+
+    while !frontier.is_empty() {
+        let cell = frontier.swap_remove(rng.below(frontier.len()));
+        let connected: Vec<usize> = grid.neighbours(cell).into_iter().filter(|&n| in_maze[n]).collect();
+        grid.carve(cell, connected[rng.below(connected.len())]);
+        in_maze[cell] = true;
+
+        for n in grid.neighbours(cell) {
+            if !in_maze[n] && !in_frontier[n] {
+                in_frontier[n] = true;
+                frontier.push(n);
+            }
+        }
+    }
+}
+
+fn find_set(sets: &mut [usize], cell: usize) -> usize {
+    let mut root = cell;
+    while sets[root] != root {
+        root = sets[root];
+    }
+    //path compression
+    let mut current = cell;
+    while sets[current] != root {
+        let next = sets[current];
+        sets[current] = root;
+        current = next;
+    }
+    root
+}
+
+fn kruskal(grid: &mut Grid, rng: &mut Rng) {
+    let mut walls = Vec::new();
+    for cell in 0..grid.len() {
+        for n in grid.neighbours(cell) {
+            if n > cell {
+                walls.push((cell, n));
+            }
+        }
+    }
+    rng.shuffle(&mut walls);
+
+    let mut sets: Vec<usize> = (0..grid.len()).collect();
+    for (a, b) in walls {
+        let (root_a, root_b) = (find_set(&mut sets, a), find_set(&mut sets, b));
+        if root_a != root_b {
+            sets[root_a] = root_b;
+            grid.carve(a, b);
+        }
+    }
+}
+
+fn wilson(grid: &mut Grid, rng: &mut Rng) {
+    let mut in_maze = vec![false; grid.len()];
+    in_maze[rng.below(grid.len())] = true;
+    let mut remaining: Vec<usize> = (0..grid.len()).filter(|&c| !in_maze[c]).collect();
+    rng.shuffle(&mut remaining);
+
+    //for every cell of the walk, the cell we left it to - revisiting a cell overwrites
+    //its exit, which erases the loop
+    let mut next = vec![usize::MAX; grid.len()];
+    for start in remaining {
+        if in_maze[start] {
+            continue;
+        }
+        let mut current = start;
+        while !in_maze[current] {
+            let options = grid.neighbours(current);
+            let step = options[rng.below(options.len())];
+            next[current] = step;
+            current = step;
+        }
+
+        current = start;
+        while !in_maze[current] {
+            in_maze[current] = true;
+            grid.carve(current, next[current]);
+            current = next[current];
+        }
+    }
+}
+
+fn eller(grid: &mut Grid, rng: &mut Rng) {
+    let width = grid.width;
+    let mut row_sets: Vec<usize> = (0..width).collect();
+    let mut next_set = width;
+
+    for y in 0..grid.height {
+        let last_row = y + 1 == grid.height;
+
+        //join adjacent cells of different sets
+        for x in 0..width - 1 {
+            if row_sets[x] != row_sets[x + 1] && (last_row || rng.chance(0.5)) {
+                grid.carve(y * width + x, y * width + x + 1);
+                let (from, to) = (row_sets[x + 1], row_sets[x]);
+                for set in row_sets.iter_mut() {
+                    if *set == from {
+                        *set = to;
+                    }
+                }
+            }
+        }
+        if last_row {
+            break;
+        }
+
+        //every set gets at least one passage down
+        let mut next_row = vec![usize::MAX; width];
+        let mut members: Vec<usize> = (0..width).collect();
+        rng.shuffle(&mut members);
+        let mut has_passage = Vec::<usize>::new();
+        for x in members {
+            let set = row_sets[x];
+            if !has_passage.contains(&set) || rng.chance(0.3) {
+                grid.carve(y * width + x, (y + 1) * width + x);
+                next_row[x] = set;
+                has_passage.push(set);
+            }
+        }
+        for set in next_row.iter_mut() {
+            if *set == usize::MAX {
+                *set = next_set;
+                next_set += 1;
+            }
+        }
+        row_sets = next_row;
+    }
+}
+
+fn remove_dead_ends(grid: &mut Grid, rng: &mut Rng, braid: f64) {
+    if braid <= 0.0 {
+        return;
+    }
+    let mut cells: Vec<usize> = (0..grid.len()).collect();
+    rng.shuffle(&mut cells);
+
+    for cell in cells {
+        if grid.degree(cell) != 1 || !rng.chance(braid) {
+            continue;
+        }
+        let closed: Vec<usize> = grid.neighbours(cell).into_iter().filter(|&n| !grid.is_open(cell, n)).collect();
+        if closed.is_empty() {
+            continue;
+        }
+        //prefer joining two dead ends at once
+        let dead_ends: Vec<usize> = closed.iter().copied().filter(|&n| grid.degree(n) == 1).collect();
+        let target = if dead_ends.is_empty() {
+            closed[rng.below(closed.len())]
+        } else {
+            dead_ends[rng.below(dead_ends.len())]
+        };
+        grid.carve(cell, target);
+    }
+}
+
+#[cfg(test)]
+const ALL_ALGORITHMS: [MazeAlgorithm; 5] = [
+    MazeAlgorithm::RecursiveBacktracker,
+    MazeAlgorithm::Prim,
+    MazeAlgorithm::Kruskal,
+    MazeAlgorithm::Wilson,
+    MazeAlgorithm::Eller,
+];
+
+#[cfg(test)]
+fn count_links(dungeon: &Dungeon, width: usize, height: usize) -> usize {
+    let mut links = 0;
+    for y in 0..height {
+        for x in 0..width {
+            for dir in [Direction::East, Direction::South] {
+                if dungeon.get_next_room(&cell_name(x, y), dir).unwrap().is_some() {
+                    links += 1;
+                }
+            }
+        }
+    }
+    links
+}
+
+#[test]
+fn test_perfect_mazes() {
+    for algorithm in ALL_ALGORITHMS {
+        let dungeon = generate_maze(6, 5, algorithm, 0.0, 42).unwrap();
+
+        //a spanning tree over 30 cells has exactly 29 passages
+        assert_eq!(count_links(&dungeon, 6, 5), 29);
+        let path = dungeon.find_path("0,0", "5,4").unwrap().unwrap();
+        assert_eq!(path[0].name, "0,0");
+        assert_eq!(path[path.len() - 1].name, "5,4");
+    }
+}
+
+#[test]
+fn test_braided_mazes() {
+    for algorithm in ALL_ALGORITHMS {
+        let dungeon = generate_maze(6, 5, algorithm, 1.0, 7).unwrap();
+        assert!(count_links(&dungeon, 6, 5) > 29);
+
+        for y in 0..5 {
+            for x in 0..6 {
+                let exits = [Direction::North, Direction::South, Direction::East, Direction::West]
+                    .iter()
+                    .filter(|dir| dungeon.get_next_room(&cell_name(x, y), **dir).unwrap().is_some())
+                    .count();
+                assert!(exits >= 2);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_maze_is_reproducible() {
+    let first = generate_maze(8, 8, MazeAlgorithm::Wilson, 0.5, 1234).unwrap();
+    let second = generate_maze(8, 8, MazeAlgorithm::Wilson, 0.5, 1234).unwrap();
+
+    for y in 0..8 {
+        for x in 0..8 {
+            for dir in [Direction::East, Direction::South] {
+                let a = first.get_next_room(&cell_name(x, y), dir).unwrap().map(|r| r.name.clone());
+                let b = second.get_next_room(&cell_name(x, y), dir).unwrap().map(|r| r.name.clone());
+                assert_eq!(a, b);
+            }
+        }
+    }
+}
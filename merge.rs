@@ -0,0 +1,163 @@
+use crate::{Direction, Dungeon, Errors};
+
+// putting two dungeons together in memory, the same way `!include` does it for files
+//
+// the aliases of the other dungeon come along, renamed like the rooms. An alias that matches a
+// room or an alias that is already there is a `DuplicateRoom` error, with every strategy.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeStrategy {
+    //a room name that is in both dungeons is a `DuplicateRoom` error
+    Error,
+    //every room of the other dungeon gets the prefix, `Prefix("wing::")` turns "Hall" into "wing::Hall"
+    Prefix(String),
+    //a room name that is in both dungeons is one room, with the exits of both and the ones of
+    //the other dungeon winning when they go the same way
+    Union,
+}
+
+impl MergeStrategy {
+    fn rename(&self, room_name: &str) -> String {
+        match self {
+            MergeStrategy::Prefix(prefix) => format!("{}{}", prefix, room_name),
+            MergeStrategy::Error | MergeStrategy::Union => room_name.to_string(),
+        }
+    }
+}
+
+// a two-way link made while merging, from a room of this dungeon to a room of the other one,
+// with the name the room has in the other dungeon
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stitch {
+    pub from: String,
+    pub direction: Direction,
+    pub to: String,
+}
+
+impl Stitch {
+    pub fn new(from: &str, direction: Direction, to: &str) -> Self {
+        Stitch { from: from.to_string(), direction, to: to.to_string() }
+    }
+}
+
+impl Dungeon {
+    pub fn merge(&mut self, other: &Dungeon, strategy: MergeStrategy) -> Result<(), Errors> {
+        self.merge_stitched(other, strategy, &[])
+    }
+
+    // the merge is one transaction, so on an error the dungeon stays as it was
+    pub fn merge_stitched(
+        &mut self,
+        other: &Dungeon,
+        strategy: MergeStrategy,
+        stitches: &[Stitch],
+    ) -> Result<(), Errors> {
+        if strategy != MergeStrategy::Union {
+            let mut names = other.room_names().map(|name| strategy.rename(name));
+            if let Some(name) = names.find(|name| self.rooms.contains_key(name)) {
+                return Err(Errors::DuplicateRoom(name));
+            }
+        }
+        for stitch in stitches {
+            self.exact_room(&stitch.from)?;
+            other.exact_room(&stitch.to)?;
+        }
+
+        self.transaction(|dungeon| {
+            dungeon.absorb(other, |name| strategy.rename(name), strategy == MergeStrategy::Union)?;
+            for stitch in stitches {
+                dungeon.set_link(&stitch.from, stitch.direction, &strategy.rename(&stitch.to))?;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+fn test_wing() -> Dungeon {
+    let mut wing = Dungeon::new();
+    wing.add_room("Hall").unwrap();
+    wing.add_room("Armory").unwrap();
+    wing.set_link("Hall", Direction::North, "Armory").unwrap();
+    wing
+}
+
+#[test]
+fn test_merging_with_prefixes_and_stitches() {
+    let mut hub = Dungeon::new();
+    hub.add_room("Hall").unwrap();
+
+    let stitches = [Stitch::new("Hall", Direction::East, "Hall")];
+    hub.merge_stitched(&test_wing(), MergeStrategy::Prefix(String::from("east::")), &stitches).unwrap();
+    hub.merge(&test_wing(), MergeStrategy::Prefix(String::from("west::"))).unwrap();
+
+    assert_eq!(
+        hub.room_names().collect::<Vec<_>>(),
+        ["Hall", "east::Hall", "east::Armory", "west::Hall", "west::Armory"]
+    );
+    assert_eq!(hub.get_next_room("Hall", Direction::East).unwrap().unwrap().name, "east::Hall");
+    assert_eq!(hub.get_next_room("east::Hall", Direction::West).unwrap().unwrap().name, "Hall");
+    assert_eq!(hub.get_next_room("west::Armory", Direction::South).unwrap().unwrap().name, "west::Hall");
+}
+
+#[test]
+fn test_merge_strategies() {
+    let mut hub = Dungeon::new();
+    hub.add_room("Hall").unwrap();
+    hub.add_room("Well").unwrap();
+    hub.set_link("Hall", Direction::West, "Well").unwrap();
+
+    let duplicate = hub.merge(&test_wing(), MergeStrategy::Error);
+    assert!(matches!(duplicate, Err(Errors::DuplicateRoom(name)) if name == "Hall"));
+    let stitches = [Stitch::new("Hall", Direction::East, "Kitchen")];
+    assert!(matches!(
+        hub.merge_stitched(&test_wing(), MergeStrategy::Prefix(String::from("x")), &stitches),
+        Err(Errors::UnknownRoom(_))
+    ));
+    //nothing changed after the errors
+    assert_eq!(hub.room_names().count(), 2);
+
+    hub.merge(&test_wing(), MergeStrategy::Union).unwrap();
+    assert_eq!(hub.room_names().collect::<Vec<_>>(), ["Hall", "Well", "Armory"]);
+    assert_eq!(hub.get_next_room("Hall", Direction::West).unwrap().unwrap().name, "Well");
+    assert_eq!(hub.get_next_room("Hall", Direction::North).unwrap().unwrap().name, "Armory");
+}
+
+#[test]
+fn test_merge_collisions_with_aliases_and_folded_names() {
+    let mut hub = Dungeon::new();
+    hub.add_room("Hub").unwrap();
+    hub.add_alias("Hub", "Armory").unwrap();
+    assert!(matches!(hub.merge(&test_wing(), MergeStrategy::Error), Err(Errors::DuplicateRoom(name)) if name == "Armory"));
+    assert_eq!(hub.room_names().collect::<Vec<_>>(), ["Hub"]);
+
+    let mut hub = Dungeon::new();
+    hub.add_room("ARMORY").unwrap();
+    hub.set_name_matching(crate::names::NameMatching::CaseFolded).unwrap();
+    assert!(matches!(hub.merge(&test_wing(), MergeStrategy::Union), Err(Errors::DuplicateRoom(_))));
+    assert_eq!(hub.room_names().collect::<Vec<_>>(), ["ARMORY"]);
+    assert!(hub.get_next_room("ARMORY", Direction::South).unwrap().is_none());
+}
+
+#[test]
+fn test_merging_aliases() {
+    let mut wing = test_wing();
+    wing.add_alias("Armory", "Arsenal").unwrap();
+    let mut hub = Dungeon::new();
+    hub.add_room("Hall").unwrap();
+    hub.add_alias("Hall", "Lobby").unwrap();
+
+    hub.merge(&wing, MergeStrategy::Prefix(String::from("east::"))).unwrap();
+    assert_eq!(hub.get_room("east::Arsenal").unwrap().name, "east::Armory");
+    hub.merge(&wing, MergeStrategy::Union).unwrap();
+    assert_eq!(hub.get_room("Arsenal").unwrap().name, "Armory");
+    //the same alias on the same room is fine for a union
+    hub.merge(&wing, MergeStrategy::Union).unwrap();
+
+    let mut clash = Dungeon::new();
+    clash.add_room("Cellar").unwrap();
+    clash.add_alias("Cellar", "Lobby").unwrap();
+    assert!(matches!(hub.merge(&clash, MergeStrategy::Error), Err(Errors::DuplicateRoom(name)) if name == "Lobby"));
+    assert!(hub.get_room("Cellar").is_err());
+    assert_eq!(hub.get_room("Lobby").unwrap().name, "Hall");
+}
@@ -0,0 +1,190 @@
+#[cfg(feature = "unicode")]
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{Dungeon, Errors, Room};
+
+// finding rooms by the names players type
+//
+// lookups (`get_room`, `get_next_room`, `find_path`, `next_hop` and everything built on them)
+// take the name of a room or one of its aliases, compared the way the dungeon's `NameMatching`
+// says. Edits (links, locks, triggers, renames, ...) still take the exact room name, so what is
+// stored in the dungeon never depends on how names are matched. No two rooms or aliases can
+// match the same text: adding one that would is a `DuplicateRoom` error.
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NameMatching {
+    //byte for byte
+    #[default]
+    Exact,
+    //without caring about case, `хол` finds `Хол`
+    CaseFolded,
+    //case folded and in unicode normalization form C, so a `й` typed as `и` and a combining
+    //breve finds the precomposed one
+    #[cfg(feature = "unicode")]
+    Nfc,
+    //like `Nfc` but in form KC, which also matches compatibility characters (`ﬁ` and `fi`,
+    //full width letters and the like)
+    #[cfg(feature = "unicode")]
+    Nfkc,
+}
+
+impl NameMatching {
+    // the text two names that match have in common
+    pub(crate) fn key(self, name: &str) -> String {
+        match self {
+            NameMatching::Exact => name.to_string(),
+            NameMatching::CaseFolded => name.to_lowercase(),
+            #[cfg(feature = "unicode")]
+            NameMatching::Nfc => name.nfc().collect::<String>().to_lowercase().nfc().collect(),
+            #[cfg(feature = "unicode")]
+            NameMatching::Nfkc => name.nfkc().collect::<String>().to_lowercase().nfkc().collect(),
+        }
+    }
+}
+
+impl Dungeon {
+    pub fn name_matching(&self) -> NameMatching {
+        self.adjacency.matching()
+    }
+
+    // fails when two rooms or aliases would match the same text, and then nothing changes
+    pub fn set_name_matching(&mut self, matching: NameMatching) -> Result<(), Errors> {
+        let mut keys = std::collections::HashSet::new();
+        for room in self.rooms() {
+            for name in std::iter::once(&room.name).chain(&room.aliases) {
+                if !keys.insert(matching.key(name)) {
+                    return Err(Errors::DuplicateRoom(name.clone()));
+                }
+            }
+        }
+        let rooms: Vec<&Room> = self.order.iter().map(|name| &self.rooms[name]).collect();
+        self.adjacency.set_matching(matching, rooms);
+        Ok(())
+    }
+
+    // the exact name of the room a name or alias matches
+    pub fn resolve_room_name(&self, room_name: &str) -> Option<&str> {
+        self.adjacency.find(room_name)
+    }
+
+    // the room's own name, for its name or an alias, what sessions and saves keep rooms by
+    pub(crate) fn own_room_name(&self, room_name: &str) -> Result<&str, Errors> {
+        Ok(&self.get_room(room_name)?.name)
+    }
+
+    // edits take the exact name of a room, not an alias or a name that only matches it
+    pub(crate) fn exact_room(&self, room_name: &str) -> Result<&Room, Errors> {
+        self.rooms.get(room_name).ok_or_else(|| Errors::UnknownRoom(room_name.to_string()))
+    }
+
+    // `DuplicateRoom` when the name is already taken by a room or an alias
+    pub(crate) fn check_name_is_free(&self, name: &str) -> Result<(), Errors> {
+        match self.adjacency.find(name) {
+            Some(_) => Err(Errors::DuplicateRoom(name.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    pub fn add_alias(&mut self, room_name: &str, alias: &str) -> Result<(), Errors> {
+        self.exact_room(room_name)?;
+        self.check_name_is_free(alias)?;
+        self.edit(|dungeon| {
+            dungeon.room_mut(room_name).unwrap().aliases.push(alias.to_string());
+            dungeon.sync_room(room_name);
+            Ok(())
+        })
+    }
+
+    // false when no room has the alias
+    pub fn remove_alias(&mut self, alias: &str) -> bool {
+        let room_name = match self.rooms().find(|room| room.aliases.iter().any(|a| a == alias)) {
+            Some(room) => room.name.clone(),
+            None => return false,
+        };
+        let _ = self.edit(|dungeon| {
+            dungeon.room_mut(&room_name).unwrap().aliases.retain(|a| a != alias);
+            dungeon.sync_room(&room_name);
+            Ok(())
+        });
+        true
+    }
+}
+
+#[cfg(test)]
+fn test_dungeon() -> Dungeon {
+    let mut dungeon = Dungeon::new();
+    dungeon.add_room("Антре").unwrap();
+    dungeon.add_room("Хол").unwrap();
+    dungeon.add_room("Стая").unwrap();
+    dungeon.set_link("Антре", crate::Direction::North, "Хол").unwrap();
+    dungeon.set_link("Хол", crate::Direction::East, "Стая").unwrap();
+    dungeon
+}
+
+#[test]
+fn test_case_folded_names() {
+    let mut dungeon = test_dungeon();
+    assert!(matches!(dungeon.get_room("хол"), Err(Errors::UnknownRoom(_))));
+
+    dungeon.set_name_matching(NameMatching::CaseFolded).unwrap();
+    assert_eq!(dungeon.get_room("хол").unwrap().name, "Хол");
+    assert_eq!(dungeon.get_next_room("АНТРЕ", crate::Direction::North).unwrap().unwrap().name, "Хол");
+    let path = dungeon.find_path("антре", "стая").unwrap().unwrap();
+    assert_eq!(path.iter().map(|room| room.name.as_str()).collect::<Vec<_>>(), ["Антре", "Хол", "Стая"]);
+    assert!(matches!(dungeon.add_room("ХОЛ"), Err(Errors::DuplicateRoom(_))));
+
+    //edits want the exact name
+    assert!(matches!(dungeon.set_link("хол", crate::Direction::West, "Стая"), Err(Errors::UnknownRoom(_))));
+
+    dungeon.set_name_matching(NameMatching::Exact).unwrap();
+    dungeon.add_room("ХОЛ").unwrap();
+    assert!(matches!(dungeon.set_name_matching(NameMatching::CaseFolded), Err(Errors::DuplicateRoom(_))));
+    assert_eq!(dungeon.name_matching(), NameMatching::Exact);
+}
+
+#[test]
+fn test_aliases() {
+    let mut dungeon = test_dungeon();
+    dungeon.add_alias("Хол", "Hall").unwrap();
+    dungeon.add_alias("Стая", "Room").unwrap();
+    assert!(matches!(dungeon.add_alias("Антре", "Hall"), Err(Errors::DuplicateRoom(_))));
+    assert!(matches!(dungeon.add_alias("Антре", "Стая"), Err(Errors::DuplicateRoom(_))));
+    assert!(matches!(dungeon.add_room("Room"), Err(Errors::DuplicateRoom(_))));
+
+    assert_eq!(dungeon.get_room("Hall").unwrap().name, "Хол");
+    assert_eq!(dungeon.find_path("Hall", "Room").unwrap().unwrap().len(), 2);
+    dungeon.set_name_matching(NameMatching::CaseFolded).unwrap();
+    assert_eq!(dungeon.resolve_room_name("room"), Some("Стая"));
+
+    //aliases go with the room
+    dungeon.rename_room("Хол", "Коридор").unwrap();
+    assert_eq!(dungeon.get_room("hall").unwrap().name, "Коридор");
+    dungeon.remove_room("Коридор").unwrap();
+    assert!(dungeon.get_room("hall").is_err());
+
+    assert!(dungeon.remove_alias("Room"));
+    assert!(!dungeon.remove_alias("Room"));
+    assert!(dungeon.get_room("room").is_err());
+}
+
+#[cfg(feature = "unicode")]
+#[test]
+fn test_normalized_names() {
+    let mut dungeon = test_dungeon();
+    dungeon.add_room("Музей").unwrap();
+    dungeon.add_room("Office").unwrap();
+    //и with a combining breve instead of й
+    let decomposed = "Музеи\u{306}";
+    assert!(dungeon.get_room(decomposed).is_err());
+
+    dungeon.set_name_matching(NameMatching::Nfc).unwrap();
+    assert_eq!(dungeon.get_room(decomposed).unwrap().name, "Музей");
+    assert_eq!(dungeon.get_room("музеи\u{306}").unwrap().name, "Музей");
+    //with an `ﬃ` ligature
+    assert!(dungeon.get_room("O\u{fb03}ce").is_err());
+
+    dungeon.set_name_matching(NameMatching::Nfkc).unwrap();
+    assert_eq!(dungeon.get_room("O\u{fb03}ce").unwrap().name, "Office");
+    assert_eq!(dungeon.get_room("ＯＦＦＩＣＥ").unwrap().name, "Office");
+}
@@ -0,0 +1,249 @@
+use crate::{triggers, Dungeon, Errors};
+
+// the dungeon text format, read one line at a time
+//
+//     line      = blank | comment | header | item | directive
+//     blank     = { space }
+//     comment   = { space } "#" [ not "#" { any } ]
+//     header    = { space } "##" { space } name { space }
+//     item      = { space } "-" { space } text { space }
+//     directive = { space } "!" { space } text { space }
+//
+// spaces are any unicode whitespace. Sections start with a header, section names don't care
+// about case:
+//
+//     Rooms     the first header of the file, every item is a room name
+//     Links     required, right after the rooms, every item is a link:
+//                   link      = room { space } "->" { space } direction { space } "->" { space } room
+//                   direction = "north" | "south" | "east" | "west" | "n" | "s" | "e" | "w"
+//               in any case, room names in links can't contain "->"
+//     Triggers  optional, right after the links, every item is a trigger, see triggers.rs
+//
+// any other header is a `LineParseError`. Blank lines and comments can go anywhere, comments take the whole line. The only
+// directive is `!include <path> [as <namespace>]` in the rooms section, see include.rs, files
+// read without a resolver can't use it. Only the current line is kept, memory use doesn't
+// depend on the size of the file.
+//
+// errors: `LineParseError` for a line that doesn't fit the grammar (or on the line after the
+// end when the file stops before the links section, 0 for an empty file), `DirectionParseError`
+// for an unknown direction, `UnknownRoom`/`DuplicateRoom` for items that don't fit the dungeon.
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum Section {
+    Start,
+    Rooms,
+    Links,
+    Triggers,
+}
+
+pub(crate) enum Line<'a> {
+    Blank,
+    Header(&'a str),
+    Item(&'a str),
+    Directive(&'a str),
+}
+
+// an `!include` line, for the caller of `Parser::line` to resolve
+pub(crate) struct Include<'a> {
+    pub(crate) path: &'a str,
+    pub(crate) namespace: Option<&'a str>,
+}
+
+// one line of the grammar, `None` when it doesn't fit any kind of line
+pub(crate) fn classify(line: &str) -> Option<Line<'_>> {
+    let line = line.trim();
+    if line.is_empty() {
+        Some(Line::Blank)
+    } else if let Some(name) = line.strip_prefix("##") {
+        let name = name.trim();
+        if name.is_empty() {
+            None
+        } else {
+            Some(Line::Header(name))
+        }
+    } else if line.starts_with('#') {
+        Some(Line::Blank)
+    } else if let Some(text) = line.strip_prefix('!') {
+        let text = text.trim();
+        if text.is_empty() {
+            None
+        } else {
+            Some(Line::Directive(text))
+        }
+    } else if let Some(text) = line.strip_prefix('-') {
+        let text = text.trim();
+        if text.is_empty() {
+            None
+        } else {
+            Some(Line::Item(text))
+        }
+    } else {
+        None
+    }
+}
+
+// the parts of `text` between the arrows, trimmed
+pub(crate) fn split_arrows(text: &str) -> Vec<&str> {
+    text.split("->").map(str::trim).collect()
+}
+
+// `text` without the leading words, which are matched without caring about case or spacing
+pub(crate) fn strip_words<'t>(text: &'t str, words: &[&str]) -> Option<&'t str> {
+    let mut rest = text.trim_start();
+    for word in words {
+        let head = rest.get(..word.len())?;
+        let after = &rest[word.len()..];
+        if !head.eq_ignore_ascii_case(word) || !(after.is_empty() || after.starts_with(char::is_whitespace)) {
+            return None;
+        }
+        rest = after.trim_start();
+    }
+    Some(rest)
+}
+
+pub(crate) struct Parser {
+    dungeon: Dungeon,
+    //the last known section that has started
+    section: Section,
+    line_number: usize,
+}
+
+impl Parser {
+    pub(crate) fn new() -> Self {
+        Parser { dungeon: Dungeon::new(), section: Section::Start, line_number: 0 }
+    }
+
+    fn error(&self) -> Errors {
+        Errors::LineParseError { line_number: self.line_number }
+    }
+
+    pub(crate) fn line_number(&self) -> usize {
+        self.line_number
+    }
+
+    pub(crate) fn dungeon_mut(&mut self) -> &mut Dungeon {
+        &mut self.dungeon
+    }
+
+    // `Some` for an include, which the caller reads and adds with `dungeon_mut`
+    pub(crate) fn line<'l>(&mut self, line: &'l str) -> Result<Option<Include<'l>>, Errors> {
+        self.line_number += 1;
+        let line = classify(line).ok_or_else(|| self.error())?;
+        if let Line::Directive(text) = line {
+            return self.directive(text).map(Some);
+        }
+        self.item(line).map(|_| None)
+    }
+
+    fn directive<'l>(&self, text: &'l str) -> Result<Include<'l>, Errors> {
+        if self.section != Section::Rooms {
+            return Err(self.error());
+        }
+        let text = strip_words(text, &["include"]).filter(|t| !t.is_empty()).ok_or_else(|| self.error())?;
+        let (path, namespace) = match text.rsplit_once(" as ") {
+            Some((path, namespace)) => (path.trim(), Some(namespace.trim())),
+            None => (text, None),
+        };
+        if path.is_empty() || namespace.is_some_and(str::is_empty) {
+            return Err(self.error());
+        }
+        Ok(Include { path, namespace })
+    }
+
+    fn item(&mut self, line: Line<'_>) -> Result<(), Errors> {
+        match line {
+            Line::Blank => Ok(()),
+            Line::Directive(_) => unreachable!(),
+            Line::Header(name) => self.header(name),
+            Line::Item(_) if self.section == Section::Start => Err(self.error()),
+            Line::Item(text) => match self.section {
+                Section::Rooms if text.contains("->") => Err(self.error()),
+                Section::Rooms => self.dungeon.add_room(text),
+                Section::Links => self.link(text),
+                Section::Triggers => self.dungeon.add_trigger(triggers::parse_trigger(text, self.line_number)?),
+                Section::Start => unreachable!(),
+            },
+        }
+    }
+
+    fn header(&mut self, name: &str) -> Result<(), Errors> {
+        let next = match name.to_lowercase().as_str() {
+            "rooms" => Section::Rooms,
+            "links" => Section::Links,
+            "triggers" => Section::Triggers,
+            _ => return Err(self.error()),
+        };
+        //known sections come once each and in this order
+        let expected = match self.section {
+            Section::Start => Section::Rooms,
+            Section::Rooms => Section::Links,
+            Section::Links | Section::Triggers => Section::Triggers,
+        };
+        if next != expected || next == self.section {
+            return Err(self.error());
+        }
+        self.section = next;
+        Ok(())
+    }
+
+    fn link(&mut self, text: &str) -> Result<(), Errors> {
+        let words = split_arrows(text);
+        if words.len() != 3 || words.iter().any(|word| word.is_empty()) {
+            return Err(self.error());
+        }
+        let direction = words[1].parse()?;
+        self.dungeon.set_link(words[0], direction, words[2])
+    }
+
+    // an error while reading the line after the last one given to the parser
+    pub(crate) fn read_error(&self, error: std::io::Error) -> Errors {
+        Errors::read_error(self.line_number + 1, error)
+    }
+
+    pub(crate) fn finish(self) -> Result<Dungeon, Errors> {
+        match self.section {
+            Section::Start if self.line_number == 0 => Err(Errors::LineParseError { line_number: 0 }),
+            Section::Start | Section::Rooms => Err(Errors::LineParseError { line_number: self.line_number + 1 }),
+            Section::Links | Section::Triggers => Ok(self.dungeon),
+        }
+    }
+}
+
+#[test]
+fn test_tolerant_grammar() {
+    let text = "
+        # a comment before the rooms
+        ##rooms
+        -Hallway
+          -   Treasure Room
+
+        # two ways to write the same direction
+        ## LINKS
+        - Hallway->n->Treasure Room
+        -Treasure Room   ->  WEST ->Hallway
+    ";
+    let dungeon = Dungeon::from_reader(text.as_bytes()).unwrap();
+    assert_eq!(dungeon.get_next_room("Hallway", crate::Direction::North).unwrap().unwrap().name, "Treasure Room");
+    assert_eq!(dungeon.get_next_room("Hallway", crate::Direction::East).unwrap().unwrap().name, "Treasure Room");
+}
+
+#[test]
+fn test_grammar_errors() {
+    let line_of = |text: &str| Dungeon::from_reader(text.as_bytes()).err().unwrap().line_number();
+
+    assert_eq!(line_of("## Rooms\n- A\n-\n## Links"), Some(3));
+    assert_eq!(line_of("## Rooms\n- A\nB\n## Links"), Some(3));
+    assert_eq!(line_of("## Rooms\n- A -> North -> A\n## Links"), Some(2));
+    assert_eq!(line_of("## Rooms\n- A\n##\n## Links"), Some(3));
+    assert_eq!(line_of("- A\n## Rooms"), Some(1));
+    assert_eq!(line_of("## Links\n## Rooms"), Some(1));
+    assert_eq!(line_of("## Rooms\n## Links\n## Rooms"), Some(3));
+    assert_eq!(line_of("## Rooms\n## Triggers\n## Links"), Some(2));
+    assert_eq!(line_of("## Rooms\n- A\n## Links\n## Notes\n- A"), Some(4));
+    assert_eq!(line_of("## Rooms\n- A\n## Links\n- A -> North"), Some(4));
+    assert_eq!(line_of("## Rooms\n- A\n## Links\n- A -> -> A"), Some(4));
+    assert_eq!(line_of("\n# only a comment\n"), Some(3));
+
+    let direction = Dungeon::from_reader("## Rooms\n- A\n## Links\n- A -> Up -> A".as_bytes());
+    assert!(matches!(direction, Err(Errors::DirectionParseError(d)) if d == "Up"));
+}
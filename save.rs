@@ -0,0 +1,337 @@
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+
+use crate::fog::KnownMap;
+use crate::session::{GameState, Session};
+use crate::{Direction, Dungeon, Errors, ALL_DIRECTIONS};
+
+// saved games use the same markdown-like style as dungeon files:
+//
+//     ## Save 1
+//     - Position: Hallway
+//     - Turn: 12
+//     ## Visited
+//     - Entrance
+//     - Hallway
+//     ## Links
+//     - Hallway -> East -> Vault
+//     ## Locked
+//     - Hallway -> North
+//     ## Items
+//     - Hallway -> lamp
+//     ## Inventory
+//     - key
+//
+// all sections are always written, in this order, and may be empty. The dungeon itself
+// isn't part of the save, a save is loaded on top of a dungeon and everything it refers to
+// is checked against it. The move history isn't saved, so a loaded game can't be undone.
+//
+// version 1 saves don't have the links section (links made during the game), they can still
+// be loaded
+
+pub const SAVE_VERSION: u32 = 2;
+
+fn io_error(e: std::io::Error) -> Errors {
+    Errors::IoError(e)
+}
+
+impl<'a> Session<'a> {
+    pub fn save<W: Write>(&self, mut writer: W) -> Result<(), Errors> {
+        writeln!(writer, "## Save {}", SAVE_VERSION).map_err(io_error)?;
+        writeln!(writer, "- Position: {}", self.current).map_err(io_error)?;
+        writeln!(writer, "- Turn: {}", self.state.turn).map_err(io_error)?;
+
+        let mut visited: Vec<&String> = self.visited.iter().collect();
+        visited.sort();
+        writeln!(writer, "## Visited").map_err(io_error)?;
+        for room in visited {
+            writeln!(writer, "- {}", room).map_err(io_error)?;
+        }
+
+        //both sides of every link are stored, so every link is written once from each side
+        let mut links: Vec<(&(String, Direction), &String)> = self.state.links.iter().collect();
+        links.sort_by_key(|((room, dir), _)| (room.clone(), ALL_DIRECTIONS.iter().position(|d| d == dir)));
+        writeln!(writer, "## Links").map_err(io_error)?;
+        for ((room, dir), other) in links {
+            writeln!(writer, "- {} -> {} -> {}", room, dir, other).map_err(io_error)?;
+        }
+
+        let mut locked: Vec<&(String, Direction)> = self.state.locked.iter().collect();
+        locked.sort_by_key(|(room, dir)| (room.clone(), ALL_DIRECTIONS.iter().position(|d| d == dir)));
+        writeln!(writer, "## Locked").map_err(io_error)?;
+        for (room, dir) in locked {
+            writeln!(writer, "- {} -> {}", room, dir).map_err(io_error)?;
+        }
+
+        let mut rooms: Vec<&String> = self.state.items.keys().collect();
+        rooms.sort();
+        writeln!(writer, "## Items").map_err(io_error)?;
+        for room in rooms {
+            for item in &self.state.items[room] {
+                writeln!(writer, "- {} -> {}", room, item).map_err(io_error)?;
+            }
+        }
+
+        writeln!(writer, "## Inventory").map_err(io_error)?;
+        for item in &self.state.inventory {
+            writeln!(writer, "- {}", item).map_err(io_error)?;
+        }
+        Ok(())
+    }
+
+    pub fn load<B: BufRead>(dungeon: &'a Dungeon, reader: B) -> Result<Self, Errors> {
+        let mut lines = Vec::new();
+        for (index, line) in reader.lines().enumerate() {
+            lines.push(line.map_err(|e| Errors::read_error(index + 1, e))?);
+        }
+        let mut parser = SaveParser { lines, line_number: 0 };
+
+        let header = parser.next_line()?;
+        let version = header
+            .strip_prefix("## Save ")
+            .and_then(|v| v.trim().parse::<u32>().ok())
+            .ok_or(Errors::LineParseError { line_number: parser.line_number })?;
+        if version == 0 || version > SAVE_VERSION {
+            return Err(Errors::UnsupportedSaveVersion(version));
+        }
+
+        let position = parser.field("Position")?;
+        let turn = parser
+            .field("Turn")?
+            .parse::<usize>()
+            .map_err(|_| Errors::LineParseError { line_number: parser.line_number })?;
+        //a room can be saved by an alias, the session keeps it by its own name
+        let check_room = |name: &str| match dungeon.get_room(name) {
+            Ok(room) => Ok(room.name.clone()),
+            Err(_) => Err(Errors::SaveMismatch(name.to_string())),
+        };
+        let position = check_room(&position)?;
+
+        let mut visited = HashSet::new();
+        for (_, room) in parser.section("## Visited")? {
+            visited.insert(check_room(&room)?);
+        }
+
+        let mut state = GameState { turn, ..GameState::default() };
+        if version >= 2 {
+            for (line_number, entry) in parser.section("## Links")? {
+                let (room, link) = pair(line_number, &entry)?;
+                let (dir, other) = pair(line_number, link)?;
+                let (room, other) = (check_room(room)?, check_room(other)?);
+                state.links.insert((room, dir.parse()?), other);
+            }
+        }
+
+        for (line_number, entry) in parser.section("## Locked")? {
+            let (room, dir) = pair(line_number, &entry)?;
+            let dir: Direction = dir.parse()?;
+            let room = check_room(room)?;
+            if state.next_room(dungeon, &room, dir)?.is_none() {
+                return Err(Errors::SaveMismatch(entry.clone()));
+            }
+            state.locked.insert((room, dir));
+        }
+
+        for (line_number, entry) in parser.section("## Items")? {
+            let (room, item) = pair(line_number, &entry)?;
+            state.items.entry(check_room(room)?).or_default().push(item.to_string());
+        }
+
+        state.inventory = parser.section("## Inventory")?.into_iter().map(|(_, item)| item).collect();
+        if parser.line_number < parser.lines.len() {
+            return Err(Errors::LineParseError { line_number: parser.line_number + 1 });
+        }
+
+        visited.insert(position.clone());
+        let mut session = Session {
+            dungeon,
+            current: position,
+            visited,
+            history: Vec::new(),
+            known: KnownMap::new(),
+            state,
+        };
+        //the player has seen everything around the rooms they have been to
+        session.explore_visited();
+        Ok(session)
+    }
+}
+
+struct SaveParser {
+    lines: Vec<String>,
+    //number of the last line that was read, starting from 1
+    line_number: usize,
+}
+
+impl SaveParser {
+    fn next_line(&mut self) -> Result<String, Errors> {
+        match self.lines.get(self.line_number) {
+            Some(line) => {
+                self.line_number += 1;
+                Ok(line.trim().to_string())
+            }
+            None => Err(Errors::LineParseError { line_number: self.line_number + 1 }),
+        }
+    }
+
+    fn field(&mut self, name: &str) -> Result<String, Errors> {
+        let line = self.next_line()?;
+        line.strip_prefix("- ")
+            .and_then(|l| l.strip_prefix(name))
+            .and_then(|l| l.strip_prefix(": "))
+            .map(|value| value.to_string())
+            .ok_or(Errors::LineParseError { line_number: self.line_number })
+    }
+
+    // the `- ` entries after a section header (with their line numbers), up to the next
+    // header or the end of the save
+    fn section(&mut self, header: &str) -> Result<Vec<(usize, String)>, Errors> {
+        if self.next_line()? != header {
+            return Err(Errors::LineParseError { line_number: self.line_number });
+        }
+        let mut entries = Vec::new();
+        while let Some(line) = self.lines.get(self.line_number) {
+            let line = line.trim();
+            if line.starts_with("## ") {
+                break;
+            }
+            self.line_number += 1;
+            match line.strip_prefix("- ") {
+                Some(entry) => entries.push((self.line_number, entry.to_string())),
+                None => return Err(Errors::LineParseError { line_number: self.line_number }),
+            }
+        }
+        Ok(entries)
+    }
+}
+
+fn pair(line_number: usize, entry: &str) -> Result<(&str, &str), Errors> {
+    entry.split_once(" -> ").ok_or(Errors::LineParseError { line_number })
+}
+
+#[cfg(test)]
+fn test_dungeon() -> Dungeon {
+    let mut dungeon = Dungeon::new();
+    dungeon.add_room("Entrance").unwrap();
+    dungeon.add_room("Hallway").unwrap();
+    dungeon.add_room("Treasure Room").unwrap();
+    dungeon.set_link("Entrance", Direction::East, "Hallway").unwrap();
+    dungeon.set_link("Hallway", Direction::North, "Treasure Room").unwrap();
+    dungeon
+}
+
+#[cfg(test)]
+const TEST_SAVE: &str = "## Save 2
+- Position: Hallway
+- Turn: 2
+## Visited
+- Entrance
+- Hallway
+## Links
+- Entrance -> South -> Treasure Room
+- Treasure Room -> North -> Entrance
+## Locked
+- Hallway -> North
+- Treasure Room -> South
+## Items
+- Entrance -> lamp
+- Treasure Room -> gold
+## Inventory
+- key
+";
+
+#[test]
+fn test_saving_and_loading() {
+    let dungeon = test_dungeon();
+    let mut session = Session::new(&dungeon, "Entrance").unwrap();
+    session.place_item("Entrance", "lamp").unwrap();
+    session.place_item("Entrance", "key").unwrap();
+    session.place_item("Treasure Room", "gold").unwrap();
+    session.lock_link("Hallway", Direction::North).unwrap();
+    session.set_link("Entrance", Direction::South, "Treasure Room").unwrap();
+    session.take("key");
+    session.move_dir(Direction::East);
+
+    let mut saved = Vec::new();
+    session.save(&mut saved).unwrap();
+    assert_eq!(String::from_utf8(saved).unwrap(), TEST_SAVE);
+
+    let loaded = Session::load(&dungeon, TEST_SAVE.as_bytes()).unwrap();
+    assert_eq!(loaded.current_room().name, "Hallway");
+    assert_eq!(loaded.turn(), 2);
+    assert!(loaded.has_visited("Entrance"));
+    assert!(loaded.is_locked("Treasure Room", Direction::South));
+    assert_eq!(loaded.items_in("Treasure Room"), ["gold"]);
+    assert_eq!(loaded.inventory(), ["key"]);
+    assert!(loaded.known_map().knows("Treasure Room"));
+    assert_eq!(loaded.get_next_room("Entrance", Direction::South).unwrap().unwrap().name, "Treasure Room");
+
+    //saves from before game links existed
+    let old = TEST_SAVE.replace("## Save 2", "## Save 1").replace(
+        "## Links\n- Entrance -> South -> Treasure Room\n- Treasure Room -> North -> Entrance\n",
+        "",
+    );
+    let loaded = Session::load(&dungeon, old.as_bytes()).unwrap();
+    assert!(loaded.get_next_room("Entrance", Direction::South).unwrap().is_none());
+    assert_eq!(loaded.inventory(), ["key"]);
+}
+
+#[test]
+fn test_loading_rooms_saved_by_alias() {
+    let mut dungeon = test_dungeon();
+    dungeon.add_alias("Hallway", "Hall").unwrap();
+    let save = TEST_SAVE.replace("Hallway", "Hall");
+    let mut loaded = Session::load(&dungeon, save.as_bytes()).unwrap();
+
+    assert_eq!(loaded.current_room().name, "Hallway");
+    assert!(loaded.is_locked("Hallway", Direction::North));
+    assert_eq!(loaded.move_dir(Direction::North), crate::session::MoveOutcome::Locked);
+    let mut saved = Vec::new();
+    loaded.save(&mut saved).unwrap();
+    assert!(String::from_utf8(saved).unwrap().contains("- Hallway -> North\n"));
+}
+
+#[test]
+fn test_loading_into_a_changed_dungeon() {
+    let mut dungeon = Dungeon::new();
+    dungeon.add_room("Entrance").unwrap();
+    dungeon.add_room("Hallway").unwrap();
+    dungeon.add_room("Vault").unwrap();
+    dungeon.set_link("Entrance", Direction::East, "Hallway").unwrap();
+    dungeon.set_link("Hallway", Direction::North, "Vault").unwrap();
+
+    assert!(matches!(
+        Session::load(&dungeon, TEST_SAVE.as_bytes()),
+        Err(Errors::SaveMismatch(name)) if name == "Treasure Room"
+    ));
+
+    let mut dungeon = Dungeon::new();
+    dungeon.add_room("Entrance").unwrap();
+    dungeon.add_room("Hallway").unwrap();
+    dungeon.add_room("Treasure Room").unwrap();
+    dungeon.set_link("Entrance", Direction::East, "Hallway").unwrap();
+    dungeon.set_link("Hallway", Direction::East, "Treasure Room").unwrap();
+    assert!(matches!(
+        Session::load(&dungeon, TEST_SAVE.as_bytes()),
+        Err(Errors::SaveMismatch(link)) if link == "Hallway -> North"
+    ));
+}
+
+#[test]
+fn test_invalid_saves() {
+    let dungeon = test_dungeon();
+
+    let newer = TEST_SAVE.replace("## Save 2", "## Save 3");
+    assert!(matches!(Session::load(&dungeon, newer.as_bytes()), Err(Errors::UnsupportedSaveVersion(3))));
+
+    let broken = TEST_SAVE.replace("- Turn: 2", "- Turn: two");
+    assert!(matches!(Session::load(&dungeon, broken.as_bytes()), Err(Errors::LineParseError { line_number: 3 })));
+
+    let broken = TEST_SAVE.replace("- Entrance -> lamp", "- Entrance lamp");
+    assert!(matches!(Session::load(&dungeon, broken.as_bytes()), Err(Errors::LineParseError { line_number: 14 })));
+
+    let broken = TEST_SAVE.replace("- Hallway -> North", "- Hallway -> Up");
+    assert!(matches!(Session::load(&dungeon, broken.as_bytes()), Err(Errors::DirectionParseError(_))));
+
+    assert!(matches!(Session::load(&dungeon, "".as_bytes()), Err(Errors::LineParseError { line_number: 1 })));
+}
@@ -0,0 +1,335 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use rhai::{Array, Dynamic, Engine, EvalAltResult, AST};
+
+use crate::session::{GameState, MoveOutcome, Session};
+use crate::triggers::TriggerEvent;
+use crate::{Direction, Dungeon, Errors};
+
+// rhai scripts that run on the same events as triggers
+//
+// a script sees the dungeon as it was when the host was created and the session that fired
+// the event, through these functions:
+//
+//     current_room() room_exists(room) next_room(room, dir) set_link(room, dir, other)
+//     is_locked(room, dir) lock(room, dir) unlock(room, dir)
+//     items(room) add_item(room, item) remove_item(room, item) inventory() has_item(item)
+//     visited(room) turn() say(text)
+//
+// directions are the strings "North", "South", "East" and "West", `next_room` gives "" when
+// there is no link. A script works on a copy of the session, which replaces the real one only
+// if the script finishes without errors, so a script that fails or runs out of its limits
+// changes nothing.
+
+#[derive(Debug, Clone, Copy)]
+pub struct ScriptLimits {
+    pub max_operations: u64,
+    pub max_time: Duration,
+    pub max_call_levels: usize,
+    pub max_string_size: usize,
+    pub max_array_size: usize,
+}
+
+impl Default for ScriptLimits {
+    fn default() -> Self {
+        ScriptLimits {
+            max_operations: 100_000,
+            max_time: Duration::from_millis(100),
+            max_call_levels: 32,
+            max_string_size: 4096,
+            max_array_size: 1024,
+        }
+    }
+}
+
+// what a running script can see and change
+struct ScriptContext {
+    current: String,
+    visited: HashSet<String>,
+    state: GameState,
+}
+
+type Shared = Rc<RefCell<Option<ScriptContext>>>;
+
+pub struct ScriptHost {
+    engine: Engine,
+    dungeon: Rc<Dungeon>,
+    scripts: Vec<(TriggerEvent, AST)>,
+    context: Shared,
+    started: Rc<Cell<Instant>>,
+}
+
+fn script_error(error: Errors) -> Box<EvalAltResult> {
+    error.to_string().into()
+}
+
+fn parse_direction(dir: &str) -> Result<Direction, Box<EvalAltResult>> {
+    dir.parse().map_err(script_error)
+}
+
+// runs `f` on the context of the running script
+fn with_context<T>(
+    context: &Shared,
+    f: impl FnOnce(&mut ScriptContext) -> Result<T, Errors>,
+) -> Result<T, Box<EvalAltResult>> {
+    let mut context = context.borrow_mut();
+    match context.as_mut() {
+        Some(context) => f(context).map_err(script_error),
+        None => Err("no script is running".into()),
+    }
+}
+
+impl ScriptHost {
+    pub fn new(dungeon: &Dungeon, limits: ScriptLimits) -> Self {
+        let mut engine = Engine::new();
+        engine.set_max_operations(limits.max_operations);
+        engine.set_max_call_levels(limits.max_call_levels);
+        engine.set_max_string_size(limits.max_string_size);
+        engine.set_max_array_size(limits.max_array_size);
+
+        let started = Rc::new(Cell::new(Instant::now()));
+        let clock = started.clone();
+        engine.on_progress(move |_| {
+            if clock.get().elapsed() > limits.max_time {
+                Some(Dynamic::from("time limit exceeded"))
+            } else {
+                None
+            }
+        });
+
+        let mut host = ScriptHost {
+            engine,
+            dungeon: Rc::new(dungeon.clone()),
+            scripts: Vec::new(),
+            context: Rc::new(RefCell::new(None)),
+            started,
+        };
+        host.register_api();
+        host
+    }
+
+    fn register_api(&mut self) {
+        let engine = &mut self.engine;
+        let (dungeon, context) = (self.dungeon.clone(), self.context.clone());
+
+        let c = context.clone();
+        engine.on_print(move |text| {
+            let _ = with_context(&c, |context| {
+                context.state.messages.push(text.to_string());
+                Ok(())
+            });
+        });
+
+        let c = context.clone();
+        engine.register_fn("current_room", move || with_context(&c, |context| Ok(context.current.clone())));
+
+        let d = dungeon.clone();
+        engine.register_fn("room_exists", move |room: &str| d.get_room(room).is_ok());
+
+        let (c, d) = (context.clone(), dungeon.clone());
+        engine.register_fn("next_room", move |room: &str, dir: &str| {
+            let dir = parse_direction(dir)?;
+            with_context(&c, |context| {
+                Ok(context.state.next_room(&d, room, dir)?.map(|r| r.name.clone()).unwrap_or_default())
+            })
+        });
+
+        let (c, d) = (context.clone(), dungeon.clone());
+        engine.register_fn("set_link", move |room: &str, dir: &str, other: &str| {
+            let dir = parse_direction(dir)?;
+            with_context(&c, |context| context.state.set_link(&d, room, dir, other))
+        });
+
+        let (c, d) = (context.clone(), dungeon.clone());
+        engine.register_fn("is_locked", move |room: &str, dir: &str| {
+            let dir = parse_direction(dir)?;
+            with_context(&c, |context| Ok(context.state.is_locked(d.resolve_room_name(room).unwrap_or(room), dir)))
+        });
+
+        let (c, d) = (context.clone(), dungeon.clone());
+        engine.register_fn("lock", move |room: &str, dir: &str| {
+            let dir = parse_direction(dir)?;
+            with_context(&c, |context| context.state.lock_link(&d, room, dir))
+        });
+
+        let (c, d) = (context.clone(), dungeon.clone());
+        engine.register_fn("unlock", move |room: &str, dir: &str| {
+            let dir = parse_direction(dir)?;
+            with_context(&c, |context| context.state.unlock_link(&d, room, dir))
+        });
+
+        let (c, d) = (context.clone(), dungeon.clone());
+        engine.register_fn("items", move |room: &str| {
+            with_context(&c, |context| {
+                let items = context.state.items.get(d.own_room_name(room)?).cloned().unwrap_or_default();
+                Ok(items.into_iter().map(Dynamic::from).collect::<Array>())
+            })
+        });
+
+        let (c, d) = (context.clone(), dungeon.clone());
+        engine.register_fn("add_item", move |room: &str, item: &str| {
+            with_context(&c, |context| context.state.place_item(&d, room, item))
+        });
+
+        let (c, d) = (context.clone(), dungeon.clone());
+        engine.register_fn("remove_item", move |room: &str, item: &str| {
+            with_context(&c, |context| {
+                let items = context.state.items.entry(d.own_room_name(room)?.to_string()).or_default();
+                match items.iter().position(|i| i == item) {
+                    Some(index) => {
+                        items.remove(index);
+                        Ok(true)
+                    }
+                    None => Ok(false),
+                }
+            })
+        });
+
+        let c = context.clone();
+        engine.register_fn("inventory", move || {
+            with_context(&c, |context| {
+                Ok(context.state.inventory.iter().cloned().map(Dynamic::from).collect::<Array>())
+            })
+        });
+
+        let c = context.clone();
+        engine.register_fn("has_item", move |item: &str| {
+            with_context(&c, |context| Ok(context.state.inventory.iter().any(|i| i == item)))
+        });
+
+        let (c, d) = (context.clone(), dungeon);
+        engine.register_fn("visited", move |room: &str| {
+            with_context(&c, |context| Ok(context.visited.contains(d.resolve_room_name(room).unwrap_or(room))))
+        });
+
+        let c = context.clone();
+        engine.register_fn("turn", move || with_context(&c, |context| Ok(context.state.turn as i64)));
+
+        let c = context;
+        engine.register_fn("say", move |text: &str| {
+            with_context(&c, |context| {
+                context.state.messages.push(text.to_string());
+                Ok(())
+            })
+        });
+    }
+
+    pub fn add_script(&mut self, event: TriggerEvent, source: &str) -> Result<(), Errors> {
+        match &event {
+            TriggerEvent::Enter(room) | TriggerEvent::Exit(room) | TriggerEvent::Traverse(room, _) => {
+                self.dungeon.get_room(room)?;
+            }
+        }
+        let ast = self.engine.compile(source).map_err(|e| Errors::ScriptError(e.to_string()))?;
+        self.scripts.push((event, ast));
+        Ok(())
+    }
+
+    // runs the scripts of the event in the order they were added, stops at the first failing one
+    pub fn fire(&self, session: &mut Session, event: &TriggerEvent) -> Result<(), Errors> {
+        for (_, ast) in self.scripts.iter().filter(|(e, _)| e == event) {
+            *self.context.borrow_mut() = Some(ScriptContext {
+                current: session.current.clone(),
+                visited: session.visited.clone(),
+                state: session.state.clone(),
+            });
+            self.started.set(Instant::now());
+
+            let result = self.engine.run_ast(ast);
+            let context = self.context.borrow_mut().take();
+            result.map_err(|e| Errors::ScriptError(e.to_string()))?;
+
+            if let Some(context) = context {
+                let new_links = context.state.links != session.state.links;
+                session.state = context.state;
+                if new_links {
+                    session.explore_visited();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // `Session::move_dir` followed by the scripts of the move
+    pub fn move_dir(&self, session: &mut Session, direction: Direction) -> Result<MoveOutcome, Errors> {
+        let outcome = session.move_dir(direction);
+        if outcome == MoveOutcome::Moved {
+            let last = session.history().last().unwrap().clone();
+            self.fire(session, &TriggerEvent::Exit(last.from.clone()))?;
+            self.fire(session, &TriggerEvent::Traverse(last.from, direction))?;
+            self.fire(session, &TriggerEvent::Enter(last.to))?;
+        }
+        Ok(outcome)
+    }
+}
+
+#[cfg(test)]
+fn test_dungeon() -> Dungeon {
+    let mut dungeon = Dungeon::new();
+    dungeon.add_room("Hallway").unwrap();
+    dungeon.add_room("Treasure Room").unwrap();
+    dungeon.add_room("Vault").unwrap();
+    dungeon.set_link("Hallway", Direction::North, "Treasure Room").unwrap();
+    dungeon
+}
+
+#[test]
+fn test_running_scripts() {
+    let dungeon = test_dungeon();
+    let mut host = ScriptHost::new(&dungeon, ScriptLimits::default());
+    host.add_script(
+        TriggerEvent::Enter(String::from("Treasure Room")),
+        r#"
+            if !visited("Vault") && next_room("Treasure Room", "East") == "" {
+                set_link(current_room(), "East", "Vault");
+                lock("Treasure Room", "South");
+                add_item("Vault", "gold");
+                say("A passage opens to the east, the door behind you shuts.");
+            }
+        "#,
+    )
+    .unwrap();
+
+    let mut session = Session::new(&dungeon, "Hallway").unwrap();
+    assert_eq!(host.move_dir(&mut session, Direction::North).unwrap(), MoveOutcome::Moved);
+    assert_eq!(session.take_messages(), ["A passage opens to the east, the door behind you shuts."]);
+    assert_eq!(session.move_dir(Direction::South), MoveOutcome::Locked);
+    assert!(session.known_map().knows("Vault"));
+
+    assert_eq!(host.move_dir(&mut session, Direction::East).unwrap(), MoveOutcome::Moved);
+    assert_eq!(session.items_in("Vault"), ["gold"]);
+}
+
+#[test]
+fn test_script_errors_and_limits() {
+    let dungeon = test_dungeon();
+    let limits = ScriptLimits { max_operations: 1_000, ..ScriptLimits::default() };
+    let mut host = ScriptHost::new(&dungeon, limits);
+
+    assert!(matches!(
+        host.add_script(TriggerEvent::Enter(String::from("Kitchen")), "say(\"hi\");"),
+        Err(Errors::UnknownRoom(_))
+    ));
+    assert!(matches!(
+        host.add_script(TriggerEvent::Enter(String::from("Vault")), "say(\"hi\""),
+        Err(Errors::ScriptError(_))
+    ));
+
+    host.add_script(TriggerEvent::Exit(String::from("Hallway")), "add_item(\"Hallway\", \"key\"); loop {}")
+        .unwrap();
+    host.add_script(TriggerEvent::Enter(String::from("Treasure Room")), "set_link(\"Vault\", \"Up\", \"Hallway\");")
+        .unwrap();
+
+    let mut session = Session::new(&dungeon, "Hallway").unwrap();
+    assert!(matches!(host.move_dir(&mut session, Direction::North), Err(Errors::ScriptError(_))));
+    //the move itself happened, but nothing the script did before it was stopped
+    assert_eq!(session.current_room().name, "Treasure Room");
+    assert!(session.items_in("Hallway").is_empty());
+
+    let event = TriggerEvent::Enter(String::from("Treasure Room"));
+    let error = host.fire(&mut session, &event).err().unwrap();
+    assert!(error.to_string().contains("unknown direction"));
+}
@@ -0,0 +1,249 @@
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::names::NameMatching;
+use crate::triggers::parse_trigger;
+use crate::{Direction, Dungeon, ExitKind, ALL_DIRECTIONS};
+
+// a dungeon is stored as a list of room names and a list of links, every link is replayed
+// through `set_link` when loading, so a bad file fails with the same errors as the text format.
+// Locks, aliases, triggers (in their text form) and the name matching come along, the lists
+// are left out when they are empty
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Dungeon")]
+struct DungeonData {
+    rooms: Vec<String>,
+    #[serde(default)]
+    links: Vec<LinkData>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    exits: Vec<ExitData>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    aliases: Vec<AliasData>,
+    //one side of every locked link
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    locks: Vec<LockData>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    triggers: Vec<String>,
+    #[serde(default, skip_serializing_if = "is_exact")]
+    name_matching: NameMatching,
+}
+
+fn is_exact(matching: &NameMatching) -> bool {
+    *matching == NameMatching::Exact
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Link")]
+struct LinkData {
+    from: String,
+    direction: Direction,
+    to: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    hidden: bool,
+}
+
+//a named exit
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Exit")]
+struct ExitData {
+    from: String,
+    name: String,
+    to: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    hidden: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Alias")]
+struct AliasData {
+    room: String,
+    alias: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Lock")]
+struct LockData {
+    room: String,
+    direction: Direction,
+}
+
+impl Dungeon {
+    fn to_data(&self) -> DungeonData {
+        let rooms: Vec<String> = self.room_names().map(String::from).collect();
+
+        //`set_link` always writes both sides, so links that only exist on one side
+        //(left over after an overwrite) go first and the two-way ones are replayed after them
+        let mut one_way = Vec::new();
+        let mut two_way = Vec::new();
+        let mut exits = Vec::new();
+        let mut aliases = Vec::new();
+        for room in &rooms {
+            for alias in &self.rooms[room].aliases {
+                aliases.push(AliasData { room: room.clone(), alias: alias.clone() });
+            }
+            for exit in self.rooms[room].next_to.iter() {
+                if let ExitKind::Named(name) = &exit.kind {
+                    exits.push(ExitData { from: room.clone(), name: name.clone(), to: exit.to.clone(), hidden: exit.hidden });
+                }
+            }
+            for direction in ALL_DIRECTIONS {
+                let next = match self.get_next_room(room, direction) {
+                    Ok(Some(next)) => next,
+                    _ => continue,
+                };
+                let back = self.get_next_room(&next.name, direction.opposite());
+                let hidden = self.rooms[room].next_to.get(&ExitKind::Compass(direction)).is_some_and(|e| e.hidden);
+                let link = LinkData { from: room.clone(), direction, to: next.name.clone(), hidden };
+                if !matches!(back, Ok(Some(back)) if back.name == *room) {
+                    one_way.push(link);
+                } else if *room < next.name
+                    || (*room == next.name && matches!(direction, Direction::North | Direction::East))
+                {
+                    two_way.push(link);
+                }
+            }
+        }
+        one_way.extend(two_way);
+
+        let mut locks: Vec<LockData> = Vec::new();
+        for room in &rooms {
+            for direction in ALL_DIRECTIONS.into_iter().filter(|dir| self.is_locked(room, *dir)) {
+                //the other side is locked by the same `lock_link`
+                let other = self.get_next_room(room, direction).ok().flatten().map(|other| other.name.as_str());
+                let listed = locks.iter().any(|lock| Some(lock.room.as_str()) == other && lock.direction == direction.opposite());
+                if !listed {
+                    locks.push(LockData { room: room.clone(), direction });
+                }
+            }
+        }
+        let triggers = self.triggers().iter().map(|trigger| trigger.to_string()).collect();
+
+        DungeonData { rooms, links: one_way, exits, aliases, locks, triggers, name_matching: self.name_matching() }
+    }
+}
+
+impl Serialize for Dungeon {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_data().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Dungeon {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = DungeonData::deserialize(deserializer)?;
+        let mut dungeon = Dungeon::new();
+        dungeon.set_name_matching(data.name_matching).map_err(D::Error::custom)?;
+        for room in &data.rooms {
+            dungeon.add_room(room).map_err(D::Error::custom)?;
+        }
+        for link in &data.links {
+            let result = if link.hidden {
+                dungeon.set_hidden_link(&link.from, link.direction, &link.to)
+            } else {
+                dungeon.set_link(&link.from, link.direction, &link.to)
+            };
+            result.map_err(D::Error::custom)?;
+        }
+        for exit in &data.exits {
+            dungeon.set_named_exit(&exit.from, &exit.name, &exit.to, exit.hidden).map_err(D::Error::custom)?;
+        }
+        for alias in &data.aliases {
+            dungeon.add_alias(&alias.room, &alias.alias).map_err(D::Error::custom)?;
+        }
+        for lock in &data.locks {
+            dungeon.lock_link(&lock.room, lock.direction).map_err(D::Error::custom)?;
+        }
+        for text in &data.triggers {
+            let trigger = parse_trigger(text, 0).map_err(|_| D::Error::custom(format!("invalid trigger \"{}\"", text)))?;
+            dungeon.add_trigger(trigger).map_err(D::Error::custom)?;
+        }
+        Ok(dungeon)
+    }
+}
+
+#[cfg(test)]
+fn test_dungeon() -> Dungeon {
+    let mut dungeon = Dungeon::new();
+    dungeon.add_room("Entrance").unwrap();
+    dungeon.add_room("Hallway").unwrap();
+    dungeon.add_room("Хол").unwrap();
+    dungeon.set_link("Entrance", Direction::East, "Hallway").unwrap();
+    dungeon.set_link("Hallway", Direction::North, "Хол").unwrap();
+    dungeon
+}
+
+#[cfg(test)]
+fn assert_same_links(first: &Dungeon, second: &Dungeon) {
+    for room in first.rooms.keys() {
+        let a: Vec<_> = first.rooms[room].next_to.iter().collect();
+        let b: Vec<_> = second.rooms[room].next_to.iter().collect();
+        assert_eq!(a, b);
+    }
+    assert_eq!(first.rooms.len(), second.rooms.len());
+}
+
+#[test]
+fn test_json_round_trip() {
+    let dungeon = test_dungeon();
+    let json = serde_json::to_string(&dungeon).unwrap();
+
+    assert_eq!(
+        json,
+        r#"{"rooms":["Entrance","Hallway","Хол"],"links":[{"from":"Entrance","direction":"East","to":"Hallway"},{"from":"Hallway","direction":"North","to":"Хол"}]}"#
+    );
+    assert_same_links(&dungeon, &serde_json::from_str(&json).unwrap());
+}
+
+#[test]
+fn test_hidden_and_named_exits_round_trip() {
+    let mut dungeon = test_dungeon();
+    dungeon.set_hidden_link("Entrance", Direction::South, "Хол").unwrap();
+    dungeon.set_named_exit("Hallway", "trapdoor", "Entrance", true).unwrap();
+    dungeon.add_alias("Хол", "Hall").unwrap();
+    let json = serde_json::to_string(&dungeon).unwrap();
+
+    assert!(json.contains(r#"{"from":"Entrance","direction":"South","to":"Хол","hidden":true}"#));
+    assert!(json.contains(r#""exits":[{"from":"Hallway","name":"trapdoor","to":"Entrance","hidden":true}]"#));
+    assert!(json.contains(r#""aliases":[{"room":"Хол","alias":"Hall"}]"#));
+    let loaded: Dungeon = serde_json::from_str(&json).unwrap();
+    assert_same_links(&dungeon, &loaded);
+    assert_eq!(loaded.get_room("Hall").unwrap().name, "Хол");
+}
+
+#[test]
+fn test_deserializing_invalid_links() {
+    let unknown = r#"{"rooms":["Entrance"],"links":[{"from":"Entrance","direction":"East","to":"Hallway"}]}"#;
+    let error = serde_json::from_str::<Dungeon>(unknown).err().unwrap();
+    assert!(error.to_string().starts_with("unknown room \"Hallway\""));
+
+    let duplicate = r#"{"rooms":["Entrance","Entrance"]}"#;
+    let error = serde_json::from_str::<Dungeon>(duplicate).err().unwrap();
+    assert!(error.to_string().starts_with("duplicate room \"Entrance\""));
+
+    let direction = r#"{"rooms":["A","B"],"links":[{"from":"A","direction":"Up","to":"B"}]}"#;
+    assert!(serde_json::from_str::<Dungeon>(direction).is_err());
+}
+
+#[test]
+fn test_locks_triggers_and_name_matching_round_trip() {
+    let mut dungeon = test_dungeon();
+    dungeon.lock_link("Hallway", Direction::North).unwrap();
+    dungeon.add_trigger(parse_trigger("on enter Hallway => unlock Hallway -> North", 1).unwrap()).unwrap();
+    dungeon.set_name_matching(NameMatching::CaseFolded).unwrap();
+    let json = serde_json::to_string(&dungeon).unwrap();
+
+    assert!(json.contains(r#""locks":[{"room":"Hallway","direction":"North"}]"#));
+    assert!(json.contains(r#""name_matching":"CaseFolded""#));
+    let loaded: Dungeon = serde_json::from_str(&json).unwrap();
+    assert_same_links(&dungeon, &loaded);
+    assert!(loaded.is_locked("Hallway", Direction::North));
+    assert!(loaded.is_locked("Хол", Direction::South));
+    assert_eq!(loaded.triggers(), dungeon.triggers());
+    assert_eq!(loaded.get_room("хол").unwrap().name, "Хол");
+
+    let trigger = r#"{"rooms":["A"],"triggers":["on enter A => lock B -> North"]}"#;
+    assert!(serde_json::from_str::<Dungeon>(trigger).err().unwrap().to_string().starts_with("unknown room \"B\""));
+    let trigger = r#"{"rooms":["A"],"triggers":["whenever"]}"#;
+    assert!(serde_json::from_str::<Dungeon>(trigger).is_err());
+}
@@ -0,0 +1,571 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::fog::KnownMap;
+use crate::triggers::{Effect, TriggerEvent};
+#[cfg(test)]
+use crate::triggers::Trigger;
+use crate::{Direction, Dungeon, Errors, ExitKind, Room, ALL_DIRECTIONS};
+
+// a player walking through a dungeon - the map itself is never changed, the session keeps
+// track of where the player is and where they have been, and of everything in the game that
+// can change: locked doors (starting from the ones locked in the dungeon), links opened
+// during the game and items. Hidden exits can be walked through but stay off the player's map
+// until they have been, from then on they are known from both sides
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MoveOutcome {
+    Moved,
+    Wall,
+    Locked,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Move {
+    pub from: String,
+    pub direction: Direction,
+    pub to: String,
+    //the move entered `to` for the first time
+    pub first_visit: bool,
+}
+
+// the part of a session that the game itself changes, kept apart so scripts can work on a
+// copy of it
+#[derive(Debug, Clone, Default)]
+pub(crate) struct GameState {
+    pub(crate) turn: usize,
+    pub(crate) locked: HashSet<(String, Direction)>,
+    //links made during the game, they take precedence over the ones of the dungeon
+    pub(crate) links: HashMap<(String, Direction), String>,
+    pub(crate) items: HashMap<String, Vec<String>>,
+    pub(crate) inventory: Vec<String>,
+    //hidden exits of the dungeon the player has gone through, and their other side
+    pub(crate) discovered: HashSet<(String, Direction)>,
+    //what triggers told the player since the last `take_messages`
+    pub(crate) messages: Vec<String>,
+}
+
+impl GameState {
+    pub(crate) fn next_room<'d>(
+        &self,
+        dungeon: &'d Dungeon,
+        room_name: &str,
+        direction: Direction,
+    ) -> Result<Option<&'d Room>, Errors> {
+        let room_name = dungeon.own_room_name(room_name)?;
+        match self.links.get(&(room_name.to_string(), direction)) {
+            Some(other) => Ok(Some(dungeon.get_room(other)?)),
+            None => dungeon.get_next_room(room_name, direction),
+        }
+    }
+
+    // the same checks and the same two-sided link as `Dungeon::set_link`, rooms given by an alias
+    // are kept by their own name like everywhere in the game state
+    pub(crate) fn set_link(
+        &mut self,
+        dungeon: &Dungeon,
+        room_name: &str,
+        direction: Direction,
+        other_room_name: &str,
+    ) -> Result<(), Errors> {
+        let room_name = dungeon.own_room_name(room_name)?;
+        let other_room_name = dungeon.own_room_name(other_room_name)?;
+        self.links.insert((room_name.to_string(), direction), other_room_name.to_string());
+        self.links.insert((other_room_name.to_string(), direction.opposite()), room_name.to_string());
+        self.locked.remove(&(room_name.to_string(), direction));
+        self.locked.remove(&(other_room_name.to_string(), direction.opposite()));
+        Ok(())
+    }
+
+    pub(crate) fn is_locked(&self, room_name: &str, direction: Direction) -> bool {
+        self.locked.contains(&(room_name.to_string(), direction))
+    }
+
+    pub(crate) fn lock_link(&mut self, dungeon: &Dungeon, room_name: &str, direction: Direction) -> Result<(), Errors> {
+        let room_name = dungeon.own_room_name(room_name)?;
+        if let Some(other) = self.next_room(dungeon, room_name, direction)? {
+            self.locked.insert((room_name.to_string(), direction));
+            self.locked.insert((other.name.clone(), direction.opposite()));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn unlock_link(&mut self, dungeon: &Dungeon, room_name: &str, direction: Direction) -> Result<(), Errors> {
+        let room_name = dungeon.own_room_name(room_name)?;
+        if let Some(other) = self.next_room(dungeon, room_name, direction)? {
+            self.locked.remove(&(room_name.to_string(), direction));
+            self.locked.remove(&(other.name.clone(), direction.opposite()));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn place_item(&mut self, dungeon: &Dungeon, room_name: &str, item: &str) -> Result<(), Errors> {
+        let room_name = dungeon.own_room_name(room_name)?;
+        self.items.entry(room_name.to_string()).or_default().push(item.to_string());
+        Ok(())
+    }
+
+    // a hidden exit of the dungeon that no game link replaces
+    pub(crate) fn is_hidden(&self, dungeon: &Dungeon, room_name: &str, direction: Direction) -> bool {
+        let key = (room_name.to_string(), direction);
+        let hidden = dungeon.rooms.get(room_name).and_then(|room| room.next_to.get(&ExitKind::Compass(direction)));
+        !self.links.contains_key(&key) && hidden.is_some_and(|exit| exit.hidden)
+    }
+
+    // what the player can see of a room, hidden exits only once they are discovered
+    pub(crate) fn exits(&self, dungeon: &Dungeon, room_name: &str) -> Result<Vec<(Direction, String)>, Errors> {
+        let room_name = dungeon.own_room_name(room_name)?;
+        let mut exits = Vec::new();
+        for direction in ALL_DIRECTIONS {
+            if self.is_hidden(dungeon, room_name, direction)
+                && !self.discovered.contains(&(room_name.to_string(), direction))
+            {
+                continue;
+            }
+            if let Some(next) = self.next_room(dungeon, room_name, direction)? {
+                exits.push((direction, next.name.clone()));
+            }
+        }
+        Ok(exits)
+    }
+}
+
+pub struct Session<'a> {
+    pub(crate) dungeon: &'a Dungeon,
+    pub(crate) current: String,
+    pub(crate) visited: HashSet<String>,
+    pub(crate) history: Vec<Move>,
+    pub(crate) known: KnownMap,
+    pub(crate) state: GameState,
+}
+
+impl<'a> Session<'a> {
+    pub fn new(dungeon: &'a Dungeon, start_room_name: &str) -> Result<Self, Errors> {
+        //the start can be an alias, the session only ever keeps the room's own name
+        let start_room_name = dungeon.get_room(start_room_name)?.name.clone();
+
+        let mut visited = HashSet::new();
+        visited.insert(start_room_name.clone());
+        let mut known = KnownMap::new();
+        known.explore(dungeon, &start_room_name)?;
+        Ok(Session {
+            dungeon,
+            current: start_room_name,
+            visited,
+            history: Vec::new(),
+            known,
+            state: GameState { locked: dungeon.locked.clone(), ..GameState::default() },
+        })
+    }
+
+    // the room's own name for an alias, unknown rooms stay as they are and aren't found
+    fn own_name<'n>(&self, room_name: &'n str) -> &'n str
+    where
+        'a: 'n,
+    {
+        self.dungeon.resolve_room_name(room_name).unwrap_or(room_name)
+    }
+
+    pub fn dungeon(&self) -> &'a Dungeon {
+        self.dungeon
+    }
+
+    pub fn current_room(&self) -> &'a Room {
+        //the dungeon is borrowed for the whole session, so the room can't disappear
+        self.dungeon.get_room(&self.current).unwrap()
+    }
+
+    pub fn steps(&self) -> usize {
+        self.history.len()
+    }
+
+    // every move attempt and item action takes a turn, undoing doesn't give it back
+    pub fn turn(&self) -> usize {
+        self.state.turn
+    }
+
+    pub fn history(&self) -> &[Move] {
+        &self.history
+    }
+
+    pub fn has_visited(&self, room_name: &str) -> bool {
+        self.visited.contains(self.own_name(room_name))
+    }
+
+    pub fn visited_count(&self) -> usize {
+        self.visited.len()
+    }
+
+    pub fn visited_percentage(&self) -> f64 {
+        self.visited.len() as f64 * 100.0 / self.dungeon.rooms.len() as f64
+    }
+
+    // like `Dungeon::get_next_room`, but with the links made during the game
+    pub fn get_next_room(&self, room_name: &str, direction: Direction) -> Result<Option<&'a Room>, Errors> {
+        self.state.next_room(self.dungeon, room_name, direction)
+    }
+
+    // the exits of the current room the player can see, hidden ones once they are discovered
+    pub fn exits(&self) -> Vec<(Direction, &'a Room)> {
+        let exits = self.state.exits(self.dungeon, &self.current).unwrap_or_default();
+        exits.into_iter().map(|(direction, name)| (direction, self.dungeon.get_room(&name).unwrap())).collect()
+    }
+
+    // the exits as players read them, `north -> Hall, east -> Tower`
+    pub fn describe_exits(&self) -> String {
+        let exits = self.exits();
+        if exits.is_empty() {
+            return String::from("there are no exits");
+        }
+        let exits: Vec<String> =
+            exits.iter().map(|(dir, room)| format!("{} -> {}", dir.to_string().to_lowercase(), room.name)).collect();
+        exits.join(", ")
+    }
+
+    // links two rooms for this session only, the dungeon stays as it is
+    pub fn set_link(&mut self, room_name: &str, direction: Direction, other_room_name: &str) -> Result<(), Errors> {
+        self.state.set_link(self.dungeon, room_name, direction, other_room_name)?;
+        self.explore_visited();
+        Ok(())
+    }
+
+    pub fn move_dir(&mut self, direction: Direction) -> MoveOutcome {
+        self.state.turn += 1;
+        let next = match self.get_next_room(&self.current, direction) {
+            Ok(Some(room)) => room,
+            _ => return MoveOutcome::Wall,
+        };
+        if self.is_locked(&self.current, direction) {
+            return MoveOutcome::Locked;
+        }
+
+        let first_visit = self.visited.insert(next.name.clone());
+        self.history.push(Move {
+            from: self.current.clone(),
+            direction,
+            to: next.name.clone(),
+            first_visit,
+        });
+        let from = std::mem::replace(&mut self.current, next.name.clone());
+        if self.state.is_hidden(self.dungeon, &from, direction) {
+            self.state.discovered.insert((from.clone(), direction));
+            self.state.discovered.insert((next.name.clone(), direction.opposite()));
+            self.explore(&from);
+        }
+        self.explore(&next.name);
+
+        self.fire(&TriggerEvent::Exit(from.clone()));
+        self.fire(&TriggerEvent::Traverse(from, direction));
+        self.fire(&TriggerEvent::Enter(next.name.clone()));
+        MoveOutcome::Moved
+    }
+
+    fn explore(&mut self, room_name: &str) {
+        //the room comes from the dungeon, so it can't be unknown
+        let exits = self.state.exits(self.dungeon, room_name).unwrap();
+        self.known.record(room_name, exits);
+    }
+
+    // a new link may lead out of a room the player has already seen
+    pub(crate) fn explore_visited(&mut self) {
+        let mut visited: Vec<String> = self.visited.iter().cloned().collect();
+        visited.sort();
+        for room in visited {
+            self.explore(&room);
+        }
+    }
+
+    fn fire(&mut self, event: &TriggerEvent) {
+        let dungeon = self.dungeon;
+        for effect in dungeon.effects_of(event) {
+            //the rooms of a trigger are checked when it's added to the dungeon
+            match effect {
+                Effect::Lock(room, dir) => self.lock_link(room, *dir).unwrap(),
+                Effect::Unlock(room, dir) => self.unlock_link(room, *dir).unwrap(),
+                Effect::AddItem { room, item } => self.place_item(room, item).unwrap(),
+                Effect::Say(text) => self.state.messages.push(text.clone()),
+            }
+        }
+    }
+
+    pub fn take_messages(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.state.messages)
+    }
+
+    pub fn is_locked(&self, room_name: &str, direction: Direction) -> bool {
+        self.state.is_locked(self.own_name(room_name), direction)
+    }
+
+    //locking a direction without a link does nothing
+    pub fn lock_link(&mut self, room_name: &str, direction: Direction) -> Result<(), Errors> {
+        self.state.lock_link(self.dungeon, room_name, direction)
+    }
+
+    pub fn unlock_link(&mut self, room_name: &str, direction: Direction) -> Result<(), Errors> {
+        self.state.unlock_link(self.dungeon, room_name, direction)
+    }
+
+    pub fn place_item(&mut self, room_name: &str, item: &str) -> Result<(), Errors> {
+        self.state.place_item(self.dungeon, room_name, item)
+    }
+
+    pub fn items_in(&self, room_name: &str) -> &[String] {
+        self.state.items.get(self.own_name(room_name)).map(|items| items.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn inventory(&self) -> &[String] {
+        &self.state.inventory
+    }
+
+    // picks an item up from the current room, false if it isn't there
+    pub fn take(&mut self, item: &str) -> bool {
+        self.state.turn += 1;
+        let items = match self.state.items.get_mut(&self.current) {
+            Some(items) => items,
+            None => return false,
+        };
+        match items.iter().position(|i| i == item) {
+            Some(index) => {
+                self.state.inventory.push(items.remove(index));
+                true
+            }
+            None => false,
+        }
+    }
+
+    // leaves an item from the inventory in the current room, false if the player doesn't have it
+    pub fn drop_item(&mut self, item: &str) -> bool {
+        self.state.turn += 1;
+        match self.state.inventory.iter().position(|i| i == item) {
+            Some(index) => {
+                let item = self.state.inventory.remove(index);
+                self.state.items.entry(self.current.clone()).or_default().push(item);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn known_map(&self) -> &KnownMap {
+        &self.known
+    }
+
+    // path from the current room that only goes through what the player has discovered
+    pub fn find_path_known(&self, end_room_name: &str) -> Result<Option<Vec<&'a Room>>, Errors> {
+        let path = self.known.find_path(&self.current, end_room_name)?;
+        Ok(path.map(|names| names.iter().map(|name| self.dungeon.get_room(name).unwrap()).collect()))
+    }
+
+    // takes back the last move, returns it or None if nothing has been done yet
+    pub fn undo(&mut self) -> Option<Move> {
+        let last = self.history.pop()?;
+        if last.first_visit {
+            self.visited.remove(&last.to);
+        }
+        self.current = last.from.clone();
+        Some(last)
+    }
+}
+
+#[cfg(test)]
+fn test_dungeon() -> Dungeon {
+    let mut dungeon = Dungeon::new();
+    dungeon.add_room("Entrance").unwrap();
+    dungeon.add_room("Hallway").unwrap();
+    dungeon.add_room("Treasure Room").unwrap();
+    dungeon.add_room("Closet").unwrap();
+    dungeon.set_link("Entrance", Direction::East, "Hallway").unwrap();
+    dungeon.set_link("Hallway", Direction::North, "Treasure Room").unwrap();
+    dungeon.set_link("Hallway", Direction::South, "Closet").unwrap();
+    dungeon
+}
+
+#[test]
+fn test_moving_around() {
+    let dungeon = test_dungeon();
+    let mut session = Session::new(&dungeon, "Entrance").unwrap();
+
+    assert_eq!(session.move_dir(Direction::North), MoveOutcome::Wall);
+    assert_eq!(session.move_dir(Direction::East), MoveOutcome::Moved);
+    assert_eq!(session.current_room().name, "Hallway");
+    assert_eq!(session.move_dir(Direction::West), MoveOutcome::Moved);
+    assert_eq!(session.move_dir(Direction::East), MoveOutcome::Moved);
+
+    assert_eq!(session.steps(), 3);
+    assert_eq!(session.visited_percentage(), 50.0);
+    assert!(session.has_visited("Hallway"));
+    assert!(!session.has_visited("Closet"));
+
+    assert!(matches!(Session::new(&dungeon, "Kitchen"), Err(Errors::UnknownRoom(_))));
+}
+
+#[test]
+fn test_locked_doors() {
+    let mut dungeon = test_dungeon();
+    dungeon.lock_link("Treasure Room", Direction::South).unwrap();
+
+    let mut session = Session::new(&dungeon, "Hallway").unwrap();
+    assert_eq!(session.move_dir(Direction::North), MoveOutcome::Locked);
+    assert_eq!(session.current_room().name, "Hallway");
+    assert_eq!(session.steps(), 0);
+    assert_eq!(session.turn(), 1);
+
+    session.unlock_link("Hallway", Direction::North).unwrap();
+    assert_eq!(session.move_dir(Direction::North), MoveOutcome::Moved);
+    //the dungeon still has the door locked for new sessions
+    assert!(dungeon.is_locked("Hallway", Direction::North));
+}
+
+#[test]
+fn test_items() {
+    let dungeon = test_dungeon();
+    let mut session = Session::new(&dungeon, "Entrance").unwrap();
+    session.place_item("Entrance", "lamp").unwrap();
+    assert!(matches!(session.place_item("Kitchen", "knife"), Err(Errors::UnknownRoom(_))));
+
+    assert!(!session.take("key"));
+    assert!(session.take("lamp"));
+    assert_eq!(session.inventory(), ["lamp"]);
+    assert!(session.items_in("Entrance").is_empty());
+
+    session.move_dir(Direction::East);
+    assert!(session.drop_item("lamp"));
+    assert!(!session.drop_item("lamp"));
+    assert_eq!(session.items_in("Hallway"), ["lamp"]);
+    assert_eq!(session.turn(), 5);
+}
+
+#[test]
+fn test_undo() {
+    let dungeon = test_dungeon();
+    let mut session = Session::new(&dungeon, "Entrance").unwrap();
+    assert!(matches!(session.find_path_known("Closet"), Err(Errors::UnknownRoom(_))));
+
+    session.move_dir(Direction::East);
+    session.move_dir(Direction::South);
+    session.move_dir(Direction::North);
+
+    let last = session.undo().unwrap();
+    assert_eq!(last.to, "Hallway");
+    assert!(session.has_visited("Closet"));
+    assert_eq!(session.current_room().name, "Closet");
+
+    session.undo();
+    assert!(!session.has_visited("Closet"));
+    assert_eq!(session.current_room().name, "Hallway");
+
+    session.undo();
+    assert!(session.undo().is_none());
+    assert_eq!(session.current_room().name, "Entrance");
+    assert_eq!(session.steps(), 0);
+    assert_eq!(session.visited_count(), 1);
+
+    //undoing doesn't make the player forget what they have seen
+    let path = session.find_path_known("Closet").unwrap().unwrap();
+    assert_eq!(path.iter().map(|room| room.name.as_str()).collect::<Vec<_>>(), ["Entrance", "Hallway", "Closet"]);
+}
+
+#[test]
+fn test_triggers() {
+    let mut dungeon = test_dungeon();
+    dungeon
+        .add_trigger(Trigger::new(
+            TriggerEvent::Enter(String::from("Treasure Room")),
+            Effect::Lock(String::from("Treasure Room"), Direction::South),
+        ))
+        .unwrap();
+    dungeon
+        .add_trigger(Trigger::new(
+            TriggerEvent::Exit(String::from("Hallway")),
+            Effect::AddItem { room: String::from("Hallway"), item: String::from("monster") },
+        ))
+        .unwrap();
+    dungeon
+        .add_trigger(Trigger::new(
+            TriggerEvent::Traverse(String::from("Hallway"), Direction::North),
+            Effect::Say(String::from("The door slams shut!")),
+        ))
+        .unwrap();
+
+    let mut session = Session::new(&dungeon, "Hallway").unwrap();
+    assert_eq!(session.move_dir(Direction::North), MoveOutcome::Moved);
+    assert_eq!(session.take_messages(), ["The door slams shut!"]);
+    assert!(session.take_messages().is_empty());
+    assert_eq!(session.items_in("Hallway"), ["monster"]);
+    assert_eq!(session.move_dir(Direction::South), MoveOutcome::Locked);
+}
+
+#[test]
+fn test_links_made_during_the_game() {
+    let dungeon = test_dungeon();
+    let mut session = Session::new(&dungeon, "Entrance").unwrap();
+
+    session.set_link("Entrance", Direction::North, "Treasure Room").unwrap();
+    assert!(matches!(session.set_link("Entrance", Direction::West, "Kitchen"), Err(Errors::UnknownRoom(_))));
+    assert!(session.known_map().knows("Treasure Room"));
+    assert_eq!(session.get_next_room("Treasure Room", Direction::South).unwrap().unwrap().name, "Entrance");
+
+    assert_eq!(session.move_dir(Direction::North), MoveOutcome::Moved);
+    assert_eq!(session.current_room().name, "Treasure Room");
+    assert!(dungeon.get_next_room("Entrance", Direction::North).unwrap().is_none());
+}
+
+#[test]
+fn test_starting_from_an_alias() {
+    let mut dungeon = test_dungeon();
+    dungeon.lock_link("Hallway", Direction::North).unwrap();
+    dungeon.add_alias("Hallway", "Hall").unwrap();
+    let mut session = Session::new(&dungeon, "Hall").unwrap();
+
+    assert_eq!(session.current_room().name, "Hallway");
+    assert!(session.has_visited("Hallway"));
+    assert_eq!(session.visited_count(), 1);
+    assert_eq!(session.move_dir(Direction::North), MoveOutcome::Locked);
+    assert_eq!(session.move_dir(Direction::West), MoveOutcome::Moved);
+    assert_eq!(session.history()[0].from, "Hallway");
+}
+
+#[test]
+fn test_game_state_by_alias() {
+    let mut dungeon = test_dungeon();
+    dungeon.add_alias("Hallway", "Hall").unwrap();
+    dungeon.add_alias("Closet", "Cupboard").unwrap();
+    let mut session = Session::new(&dungeon, "Entrance").unwrap();
+    session.move_dir(Direction::East);
+
+    session.lock_link("Hall", Direction::North).unwrap();
+    assert!(session.is_locked("Hallway", Direction::North));
+    assert!(session.is_locked("Hall", Direction::North));
+    assert_eq!(session.move_dir(Direction::North), MoveOutcome::Locked);
+
+    session.set_link("Hall", Direction::East, "Cupboard").unwrap();
+    assert_eq!(session.move_dir(Direction::East), MoveOutcome::Moved);
+    assert_eq!(session.current_room().name, "Closet");
+    assert!(session.has_visited("Cupboard"));
+
+    session.place_item("Hall", "lamp").unwrap();
+    assert_eq!(session.items_in("Hallway"), ["lamp"]);
+    assert_eq!(session.items_in("Hall"), ["lamp"]);
+}
+
+#[test]
+fn test_discovering_hidden_exits() {
+    let mut dungeon = test_dungeon();
+    dungeon.set_hidden_link("Entrance", Direction::South, "Treasure Room").unwrap();
+    let mut session = Session::new(&dungeon, "Entrance").unwrap();
+    assert!(matches!(session.find_path_known("Treasure Room"), Err(Errors::UnknownRoom(_))));
+    assert_eq!(session.describe_exits(), "east -> Hallway");
+    session.move_dir(Direction::East);
+    session.move_dir(Direction::North);
+    assert_eq!(session.find_path_known("Entrance").unwrap().unwrap().len(), 3);
+
+    //the secret door only shows up once the player has gone through it
+    let mut session = Session::new(&dungeon, "Treasure Room").unwrap();
+    assert!(!session.known_map().knows("Entrance"));
+    assert_eq!(session.move_dir(Direction::North), MoveOutcome::Moved);
+    assert_eq!(session.current_room().name, "Entrance");
+    assert_eq!(session.find_path_known("Treasure Room").unwrap().unwrap().len(), 2);
+    assert_eq!(session.describe_exits(), "south -> Treasure Room, east -> Hallway");
+    //from both sides
+    let exits = session.known_map().known_exits("Treasure Room").unwrap();
+    assert!(exits.contains(&(Direction::North, String::from("Entrance"))));
+}
@@ -0,0 +1,119 @@
+use std::mem;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+use crate::events::{DungeonEvent, ObserverId, Observers};
+use crate::{Dungeon, Errors};
+
+// one dungeon for many threads, copy-on-write
+//
+// readers take a snapshot, an `Arc` of the dungeon as it is, and ask it anything for as long
+// as they like. An edit works on a copy and swaps it in when it's done, the only lock readers
+// ever wait for is the one around that swap. Snapshots taken before stay as they were, sessions
+// keep playing on the map they started on. Edits happen one at a time, each one is a
+// transaction: when it fails nothing is swapped in. Every edit copies the whole dungeon, so
+// this is for maps that are read a lot and changed now and then.
+//
+// a `Dungeon` is `Send` and `Sync` too, observers included, so it can also go behind a lock
+// of its own when readers may wait for writers.
+
+pub struct SharedDungeon {
+    current: RwLock<Arc<Dungeon>>,
+    //held through a whole edit, with the observers of the shared dungeon, which move into the
+    //copy while it's edited
+    editing: Mutex<Observers>,
+}
+
+impl SharedDungeon {
+    pub fn new(dungeon: Dungeon) -> Self {
+        SharedDungeon { current: RwLock::new(Arc::new(dungeon)), editing: Mutex::new(Observers::default()) }
+    }
+
+    pub fn snapshot(&self) -> Arc<Dungeon> {
+        self.current.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    pub fn edit<T>(&self, edit: impl FnOnce(&mut Dungeon) -> Result<T, Errors>) -> Result<T, Errors> {
+        let mut observers = self.editing.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut copy = (*self.snapshot()).clone();
+        copy.observers = mem::take(&mut *observers);
+        let result = copy.transaction(edit);
+        *observers = mem::take(&mut copy.observers);
+        if result.is_ok() {
+            *self.current.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(copy);
+        }
+        result
+    }
+
+    // observers hear about the edits made through `edit`, while they happen
+    pub fn subscribe(&self, observer: impl FnMut(&DungeonEvent) + Send + Sync + 'static) -> ObserverId {
+        self.editing.lock().unwrap_or_else(PoisonError::into_inner).subscribe(observer)
+    }
+
+    pub fn unsubscribe(&self, id: ObserverId) -> bool {
+        self.editing.lock().unwrap_or_else(PoisonError::into_inner).unsubscribe(id)
+    }
+}
+
+impl From<Dungeon> for SharedDungeon {
+    fn from(dungeon: Dungeon) -> Self {
+        SharedDungeon::new(dungeon)
+    }
+}
+
+#[test]
+fn test_dungeons_can_be_shared() {
+    fn shared<T: Send + Sync>() {}
+    shared::<Dungeon>();
+    shared::<SharedDungeon>();
+}
+
+#[test]
+fn test_reading_while_editing() {
+    use crate::Direction;
+
+    let mut dungeon = Dungeon::new();
+    dungeon.add_room("Entrance").unwrap();
+    let shared = Arc::new(SharedDungeon::new(dungeon));
+    let events = Arc::new(Mutex::new(0));
+    let counter = events.clone();
+    shared.subscribe(move |_| *counter.lock().unwrap() += 1);
+
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                for _ in 0..200 {
+                    //every snapshot has whole edits, a room is there with its link or not at all
+                    let snapshot = shared.snapshot();
+                    for room in snapshot.room_names().filter(|name| *name != "Entrance") {
+                        assert_eq!(snapshot.find_path("Entrance", room).unwrap().unwrap().len(), 2);
+                    }
+                }
+            })
+        })
+        .collect();
+
+    let before = shared.snapshot();
+    for i in 0..50 {
+        let room = format!("Room {}", i);
+        shared
+            .edit(|d| {
+                d.add_room(&room)?;
+                d.set_named_exit("Entrance", &room, &room, false)
+            })
+            .unwrap();
+    }
+    let failed = shared.edit(|d| {
+        d.add_room("Room 50")?;
+        d.set_link("Room 50", Direction::North, "Attic")
+    });
+    assert!(failed.is_err());
+    for reader in readers {
+        reader.join().unwrap();
+    }
+
+    assert_eq!(before.room_names().count(), 1);
+    assert_eq!(shared.snapshot().room_names().count(), 51);
+    //two events for every edit, and the room the failed one added and took back
+    assert_eq!(*events.lock().unwrap(), 102);
+}
@@ -0,0 +1,219 @@
+use std::fmt;
+
+use crate::parser::{split_arrows, strip_words};
+use crate::{Direction, Dungeon, Errors};
+
+// rules that fire while a session moves through the dungeon
+//
+// in a dungeon file they go in an optional last section:
+//
+//     ## Triggers
+//     - on enter Treasure Room => lock Treasure Room -> South
+//     - on exit Hallway => say A monster appears!
+//     - on traverse Hallway -> North => unlock Vault -> West
+//     - on enter Vault => add Vault -> gold
+//
+// a move from A to B fires `exit A`, then `traverse A -> <direction>`, then `enter B`, and
+// the triggers of each event fire in the order they were declared
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TriggerEvent {
+    Enter(String),
+    Exit(String),
+    Traverse(String, Direction),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    Lock(String, Direction),
+    Unlock(String, Direction),
+    AddItem { room: String, item: String },
+    //shown to the player, front-ends can also use it for anything the library doesn't model
+    Say(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trigger {
+    pub event: TriggerEvent,
+    pub effect: Effect,
+}
+
+impl Trigger {
+    pub fn new(event: TriggerEvent, effect: Effect) -> Self {
+        Trigger { event, effect }
+    }
+
+    pub(crate) fn rooms(&self) -> Vec<&str> {
+        let mut rooms = Vec::new();
+        match &self.event {
+            TriggerEvent::Enter(room) | TriggerEvent::Exit(room) | TriggerEvent::Traverse(room, _) => {
+                rooms.push(room.as_str())
+            }
+        }
+        match &self.effect {
+            Effect::Lock(room, _) | Effect::Unlock(room, _) | Effect::AddItem { room, .. } => rooms.push(room.as_str()),
+            Effect::Say(_) => {}
+        }
+        rooms
+    }
+
+    pub(crate) fn rooms_mut(&mut self) -> Vec<&mut String> {
+        let mut rooms = Vec::new();
+        match &mut self.event {
+            TriggerEvent::Enter(room) | TriggerEvent::Exit(room) | TriggerEvent::Traverse(room, _) => rooms.push(room),
+        }
+        match &mut self.effect {
+            Effect::Lock(room, _) | Effect::Unlock(room, _) | Effect::AddItem { room, .. } => rooms.push(room),
+            Effect::Say(_) => {}
+        }
+        rooms
+    }
+}
+
+impl fmt::Display for TriggerEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TriggerEvent::Enter(room) => write!(f, "on enter {}", room),
+            TriggerEvent::Exit(room) => write!(f, "on exit {}", room),
+            TriggerEvent::Traverse(room, dir) => write!(f, "on traverse {} -> {}", room, dir),
+        }
+    }
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Effect::Lock(room, dir) => write!(f, "lock {} -> {}", room, dir),
+            Effect::Unlock(room, dir) => write!(f, "unlock {} -> {}", room, dir),
+            Effect::AddItem { room, item } => write!(f, "add {} -> {}", room, item),
+            Effect::Say(text) => write!(f, "say {}", text),
+        }
+    }
+}
+
+// the same syntax as a line of the triggers section, without the leading `- `
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} => {}", self.event, self.effect)
+    }
+}
+
+fn parse_room_and_direction(text: &str, line_number: usize) -> Result<(String, Direction), Errors> {
+    match split_arrows(text)[..] {
+        [room, dir] if !room.is_empty() => Ok((room.to_string(), dir.parse()?)),
+        _ => Err(Errors::LineParseError { line_number }),
+    }
+}
+
+// a line of the triggers section, without the leading `-`. Keywords don't care about case and
+// spacing, like the rest of the grammar in parser.rs
+pub(crate) fn parse_trigger(line: &str, line_number: usize) -> Result<Trigger, Errors> {
+    let (event, effect) = line.split_once("=>").ok_or(Errors::LineParseError { line_number })?;
+
+    let event = if let Some(room) = strip_words(event, &["on", "enter"]) {
+        TriggerEvent::Enter(room.trim().to_string())
+    } else if let Some(room) = strip_words(event, &["on", "exit"]) {
+        TriggerEvent::Exit(room.trim().to_string())
+    } else if let Some(link) = strip_words(event, &["on", "traverse"]) {
+        let (room, dir) = parse_room_and_direction(link, line_number)?;
+        TriggerEvent::Traverse(room, dir)
+    } else {
+        return Err(Errors::LineParseError { line_number });
+    };
+
+    let effect = if let Some(link) = strip_words(effect, &["lock"]) {
+        let (room, dir) = parse_room_and_direction(link, line_number)?;
+        Effect::Lock(room, dir)
+    } else if let Some(link) = strip_words(effect, &["unlock"]) {
+        let (room, dir) = parse_room_and_direction(link, line_number)?;
+        Effect::Unlock(room, dir)
+    } else if let Some(placement) = strip_words(effect, &["add"]) {
+        match split_arrows(placement)[..] {
+            [room, item] if !room.is_empty() && !item.is_empty() => {
+                Effect::AddItem { room: room.to_string(), item: item.to_string() }
+            }
+            _ => return Err(Errors::LineParseError { line_number }),
+        }
+    } else if let Some(text) = strip_words(effect, &["say"]) {
+        Effect::Say(text.trim().to_string())
+    } else {
+        return Err(Errors::LineParseError { line_number });
+    };
+
+    Ok(Trigger { event, effect })
+}
+
+impl Dungeon {
+    pub fn add_trigger(&mut self, trigger: Trigger) -> Result<(), Errors> {
+        for room in trigger.rooms() {
+            self.exact_room(room)?;
+        }
+        self.edit(|dungeon| {
+            dungeon.triggers_mut().push(trigger);
+            Ok(())
+        })
+    }
+
+    pub fn triggers(&self) -> &[Trigger] {
+        &self.triggers
+    }
+
+    // the effects of every trigger for the event, in the order the triggers were added
+    pub fn effects_of(&self, event: &TriggerEvent) -> impl Iterator<Item = &Effect> {
+        let event = event.clone();
+        self.triggers.iter().filter(move |t| t.event == event).map(|t| &t.effect)
+    }
+}
+
+#[cfg(test)]
+const TEST_INPUT: &str = "## Rooms
+- Hallway
+- Treasure Room
+
+## Links
+- Hallway -> North -> Treasure Room
+
+## Triggers
+- on enter Treasure Room => lock Treasure Room -> South
+- on traverse Hallway -> North => say The door slams shut!
+- on exit Treasure Room => add Hallway -> monster";
+
+#[test]
+fn test_parsing_triggers() {
+    let dungeon = Dungeon::from_reader(TEST_INPUT.as_bytes()).unwrap();
+
+    assert_eq!(
+        dungeon.triggers(),
+        [
+            Trigger::new(
+                TriggerEvent::Enter(String::from("Treasure Room")),
+                Effect::Lock(String::from("Treasure Room"), Direction::South)
+            ),
+            Trigger::new(
+                TriggerEvent::Traverse(String::from("Hallway"), Direction::North),
+                Effect::Say(String::from("The door slams shut!"))
+            ),
+            Trigger::new(
+                TriggerEvent::Exit(String::from("Treasure Room")),
+                Effect::AddItem { room: String::from("Hallway"), item: String::from("monster") }
+            ),
+        ]
+    );
+    //printing a trigger gives back its line
+    assert_eq!(format!("- {}", dungeon.triggers()[2]), TEST_INPUT.lines().last().unwrap());
+}
+
+#[test]
+fn test_invalid_triggers() {
+    let unknown = TEST_INPUT.replace("add Hallway", "add Kitchen");
+    assert!(matches!(Dungeon::from_reader(unknown.as_bytes()), Err(Errors::UnknownRoom(_))));
+
+    let direction = TEST_INPUT.replace("Hallway -> North =>", "Hallway -> Up =>");
+    assert!(matches!(Dungeon::from_reader(direction.as_bytes()), Err(Errors::DirectionParseError(_))));
+
+    let syntax = TEST_INPUT.replace("on exit", "when leaving");
+    assert!(matches!(Dungeon::from_reader(syntax.as_bytes()), Err(Errors::LineParseError { .. })));
+
+    let tolerant = TEST_INPUT.replace("- on enter Treasure Room => lock", "-On  ENTER Treasure Room=>LOCK");
+    assert_eq!(Dungeon::from_reader(tolerant.as_bytes()).unwrap().triggers().len(), 3);
+}
@@ -0,0 +1,364 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::io::BufRead;
+
+use crate::parser::{classify, split_arrows, strip_words, Line, Parser};
+use crate::{Dungeon, Errors, ExitKind, ALL_DIRECTIONS};
+
+// several dungeons, one for each level, joined by stairs and portals
+//
+// in a text file every level is a `## Level <name>` block holding an ordinary dungeon, and the
+// passages between levels go in a last section:
+//
+//     ## Level Ground
+//     ## Rooms
+//     - Hallway
+//     - Kitchen
+//
+//     ## Links
+//     - Hallway -> North -> Kitchen
+//
+//     ## Level Cellar
+//     ## Rooms
+//     - Wine Cellar
+//
+//     ## Links
+//
+//     ## Passages
+//     - Ground / Hallway -> Down -> Cellar / Wine Cellar
+//     - Cellar / Wine Cellar -> Portal Mirror -> Ground / Kitchen
+//
+// stairs go both ways, `Down` from one room adds `Up` from the other, portals only go one way.
+// Headers, comments and spacing follow the grammar in parser.rs, and so do `Up`, `Down` and
+// `Portal`, which don't care about case.
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Place {
+    pub level: String,
+    pub room: String,
+}
+
+impl Place {
+    pub fn new(level: &str, room: &str) -> Self {
+        Place { level: level.to_string(), room: room.to_string() }
+    }
+}
+
+impl fmt::Display for Place {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} / {}", self.level, self.room)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Passage {
+    Up,
+    Down,
+    Portal(String),
+}
+
+impl fmt::Display for Passage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Passage::Up => write!(f, "Up"),
+            Passage::Down => write!(f, "Down"),
+            Passage::Portal(name) => write!(f, "Portal {}", name),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct World {
+    //levels in the order they were added
+    order: Vec<String>,
+    levels: HashMap<String, Dungeon>,
+    passages: HashMap<Place, Vec<(Passage, Place)>>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_level(&mut self, name: &str, dungeon: Dungeon) -> Result<(), Errors> {
+        if self.levels.contains_key(name) {
+            return Err(Errors::DuplicateLevel(name.to_string()));
+        }
+        self.order.push(name.to_string());
+        self.levels.insert(name.to_string(), dungeon);
+        Ok(())
+    }
+
+    pub fn level(&self, name: &str) -> Result<&Dungeon, Errors> {
+        self.levels.get(name).ok_or_else(|| Errors::UnknownLevel(name.to_string()))
+    }
+
+    pub fn level_mut(&mut self, name: &str) -> Result<&mut Dungeon, Errors> {
+        self.levels.get_mut(name).ok_or_else(|| Errors::UnknownLevel(name.to_string()))
+    }
+
+    pub fn level_names(&self) -> &[String] {
+        &self.order
+    }
+
+    fn check(&self, place: &Place) -> Result<(), Errors> {
+        self.level(&place.level)?.get_room(&place.room)?;
+        Ok(())
+    }
+
+    // replaces a passage with the same name out of the room, like `Dungeon::set_link`
+    fn set_passage(&mut self, from: &Place, passage: Passage, to: &Place) {
+        let passages = self.passages.entry(from.clone()).or_default();
+        passages.retain(|(p, _)| *p != passage);
+        passages.push((passage, to.clone()));
+    }
+
+    // `lower` gets stairs going up to `upper` and `upper` gets stairs going down to `lower`
+    pub fn add_stairs(&mut self, lower: &Place, upper: &Place) -> Result<(), Errors> {
+        self.check(lower)?;
+        self.check(upper)?;
+        self.set_passage(lower, Passage::Up, upper);
+        self.set_passage(upper, Passage::Down, lower);
+        Ok(())
+    }
+
+    pub fn add_portal(&mut self, name: &str, from: &Place, to: &Place) -> Result<(), Errors> {
+        self.check(from)?;
+        self.check(to)?;
+        self.set_passage(from, Passage::Portal(name.to_string()), to);
+        Ok(())
+    }
+
+    pub fn add_passage(&mut self, from: &Place, passage: Passage, to: &Place) -> Result<(), Errors> {
+        match passage {
+            Passage::Up => self.add_stairs(from, to),
+            Passage::Down => self.add_stairs(to, from),
+            Passage::Portal(name) => self.add_portal(&name, from, to),
+        }
+    }
+
+    // the stairs and portals out of a room, in the order they were added
+    pub fn passages(&self, place: &Place) -> Result<&[(Passage, Place)], Errors> {
+        self.check(place)?;
+        Ok(self.passages.get(place).map(Vec::as_slice).unwrap_or_default())
+    }
+
+    pub fn next_place(&self, place: &Place, passage: &Passage) -> Result<Option<&Place>, Errors> {
+        Ok(self.passages(place)?.iter().find(|(p, _)| p == passage).map(|(_, to)| to))
+    }
+
+    // every place one step away, through a compass direction first and then through a passage
+    fn neighbours(&self, place: &Place, use_hidden: bool) -> Vec<Place> {
+        let mut neighbours = Vec::new();
+        if let Ok(room) = self.level(&place.level).and_then(|dungeon| dungeon.get_room(&place.room)) {
+            for dir in ALL_DIRECTIONS {
+                match room.next_to.get(&ExitKind::Compass(dir)) {
+                    Some(exit) if use_hidden || !exit.hidden => neighbours.push(Place::new(&place.level, &exit.to)),
+                    _ => {}
+                }
+            }
+        }
+        if let Some(passages) = self.passages.get(place) {
+            neighbours.extend(passages.iter().map(|(_, to)| to.clone()));
+        }
+        neighbours
+    }
+
+    // a shortest path between two rooms that may be on different levels, hidden exits are left
+    // out like in `Dungeon::find_path`
+    pub fn find_path(&self, start: &Place, end: &Place) -> Result<Option<Vec<Place>>, Errors> {
+        self.find_path_through(start, end, false)
+    }
+
+    pub fn find_path_with_hidden(&self, start: &Place, end: &Place) -> Result<Option<Vec<Place>>, Errors> {
+        self.find_path_through(start, end, true)
+    }
+
+    fn find_path_through(&self, start: &Place, end: &Place, use_hidden: bool) -> Result<Option<Vec<Place>>, Errors> {
+        self.check(start)?;
+        self.check(end)?;
+
+        let mut previous = HashMap::<Place, Place>::new();
+        let mut seen = HashSet::from([start.clone()]);
+        let mut queue = VecDeque::from([start.clone()]);
+        while let Some(place) = queue.pop_front() {
+            if place == *end {
+                let mut path = vec![place];
+                while let Some(before) = previous.get(path.last().unwrap()) {
+                    path.push(before.clone());
+                }
+                path.reverse();
+                return Ok(Some(path));
+            }
+            for next in self.neighbours(&place, use_hidden) {
+                if seen.insert(next.clone()) {
+                    previous.insert(next.clone(), place.clone());
+                    queue.push_back(next);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // levels are read with the dungeon parser, one line at a time like a single dungeon
+    pub fn from_reader<B: BufRead>(reader: B) -> Result<Self, Errors> {
+        let mut world = World::new();
+        //the level being read: its name, the line of its header and its parser
+        let mut level: Option<(String, usize, Parser)> = None;
+        let mut in_passages = false;
+
+        for (index, line) in reader.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.map_err(|e| Errors::read_error(line_number, e))?;
+            let header = match classify(&line) {
+                Some(Line::Header(name)) => Some(name),
+                _ => None,
+            };
+
+            if let Some(name) = header.and_then(|name| strip_words(name, &["level"])) {
+                if in_passages || name.is_empty() {
+                    return Err(Errors::LineParseError { line_number });
+                }
+                world.finish_level(level.take())?;
+                level = Some((name.to_string(), line_number, Parser::new()));
+            } else if header.is_some_and(|name| name.eq_ignore_ascii_case("passages")) && level.is_some() {
+                world.finish_level(level.take())?;
+                in_passages = true;
+            } else if in_passages {
+                match classify(&line) {
+                    Some(Line::Blank) => {}
+                    Some(Line::Item(text)) => {
+                        let (from, passage, to) = parse_passage(text, line_number)?;
+                        world.add_passage(&from, passage, &to)?;
+                    }
+                    _ => return Err(Errors::LineParseError { line_number }),
+                }
+            } else if let Some((_, header, parser)) = level.as_mut() {
+                if parser.line(&line).map_err(|e| in_file(*header, e))?.is_some() {
+                    return Err(Errors::LineParseError { line_number });
+                }
+            } else if !matches!(classify(&line), Some(Line::Blank)) {
+                return Err(Errors::LineParseError { line_number });
+            }
+        }
+        world.finish_level(level)?;
+        Ok(world)
+    }
+
+    fn finish_level(&mut self, level: Option<(String, usize, Parser)>) -> Result<(), Errors> {
+        if let Some((name, header, parser)) = level {
+            let dungeon = parser.finish().map_err(|e| in_file(header, e))?;
+            self.add_level(&name, dungeon)?;
+        }
+        Ok(())
+    }
+}
+
+// line numbers of a level count from its header, this makes them count from the top of the file
+fn in_file(header: usize, error: Errors) -> Errors {
+    match error {
+        Errors::LineParseError { line_number } => Errors::LineParseError { line_number: header + line_number },
+        error => error,
+    }
+}
+
+fn parse_place(text: &str, line_number: usize) -> Result<Place, Errors> {
+    match text.split_once('/') {
+        Some((level, room)) if !level.trim().is_empty() && !room.trim().is_empty() => {
+            Ok(Place::new(level.trim(), room.trim()))
+        }
+        _ => Err(Errors::LineParseError { line_number }),
+    }
+}
+
+// an item of the passages section, `level / room -> passage -> level / room`
+fn parse_passage(text: &str, line_number: usize) -> Result<(Place, Passage, Place), Errors> {
+    let words = split_arrows(text);
+    if words.len() != 3 {
+        return Err(Errors::LineParseError { line_number });
+    }
+    let passage = if strip_words(words[1], &["up"]).is_some_and(str::is_empty) {
+        Passage::Up
+    } else if strip_words(words[1], &["down"]).is_some_and(str::is_empty) {
+        Passage::Down
+    } else if let Some(name) = strip_words(words[1], &["portal"]).filter(|name| !name.is_empty()) {
+        Passage::Portal(name.to_string())
+    } else {
+        return Err(Errors::DirectionParseError(words[1].to_string()));
+    };
+    Ok((parse_place(words[0], line_number)?, passage, parse_place(words[2], line_number)?))
+}
+
+#[cfg(test)]
+const TEST_INPUT: &str = "## Level Ground
+## Rooms
+- Hallway
+- Kitchen
+
+## Links
+- Hallway -> North -> Kitchen
+
+## Level Cellar
+## Rooms
+- Wine Cellar
+- Crypt
+
+## Links
+- Wine Cellar -> East -> Crypt
+
+## Passages
+- Ground / Hallway -> Down -> Cellar / Wine Cellar
+- Cellar / Crypt -> Portal Mirror -> Ground / Kitchen";
+
+#[test]
+fn test_parsing_world() {
+    let world = World::from_reader(TEST_INPUT.as_bytes()).unwrap();
+
+    assert_eq!(world.level_names(), ["Ground", "Cellar"]);
+    assert!(world.level("Cellar").unwrap().get_room("Crypt").is_ok());
+
+    let cellar = Place::new("Cellar", "Wine Cellar");
+    assert_eq!(world.next_place(&cellar, &Passage::Up).unwrap(), Some(&Place::new("Ground", "Hallway")));
+    let crypt = Place::new("Cellar", "Crypt");
+    let mirror = Passage::Portal(String::from("Mirror"));
+    assert_eq!(world.next_place(&crypt, &mirror).unwrap(), Some(&Place::new("Ground", "Kitchen")));
+    //portals only go one way
+    assert!(world.passages(&Place::new("Ground", "Kitchen")).unwrap().is_empty());
+
+    let path = world.find_path(&Place::new("Ground", "Hallway"), &crypt).unwrap().unwrap();
+    assert_eq!(path.iter().map(|p| p.to_string()).collect::<Vec<_>>(), [
+        "Ground / Hallway",
+        "Cellar / Wine Cellar",
+        "Cellar / Crypt"
+    ]);
+    let path = world.find_path(&crypt, &Place::new("Ground", "Kitchen")).unwrap().unwrap();
+    assert_eq!(path, [crypt.clone(), Place::new("Ground", "Kitchen")]);
+}
+
+#[test]
+fn test_world_paths_through_hidden_exits() {
+    let mut world = World::from_reader(TEST_INPUT.as_bytes()).unwrap();
+    let ground = world.level_mut("Ground").unwrap();
+    ground.add_room("Pantry").unwrap();
+    ground.set_hidden_link("Kitchen", crate::Direction::East, "Pantry").unwrap();
+
+    let (hallway, pantry) = (Place::new("Ground", "Hallway"), Place::new("Ground", "Pantry"));
+    assert_eq!(world.find_path(&hallway, &pantry).unwrap(), None);
+    assert_eq!(world.find_path_with_hidden(&hallway, &pantry).unwrap().unwrap().len(), 3);
+}
+
+#[test]
+fn test_invalid_world() {
+    let level = TEST_INPUT.replace("- Ground / Hallway", "- Attic / Hallway");
+    assert!(matches!(World::from_reader(level.as_bytes()), Err(Errors::UnknownLevel(_))));
+
+    let duplicate = TEST_INPUT.replace("## Level Cellar", "## Level Ground");
+    assert!(matches!(World::from_reader(duplicate.as_bytes()), Err(Errors::DuplicateLevel(_))));
+
+    let passage = TEST_INPUT.replace("-> Down ->", "-> Sideways ->");
+    assert!(matches!(World::from_reader(passage.as_bytes()), Err(Errors::DirectionParseError(_))));
+
+    //line numbers count from the top of the file, not of the level
+    let syntax = TEST_INPUT.replace("- Crypt\n", "Crypt\n");
+    assert!(matches!(World::from_reader(syntax.as_bytes()), Err(Errors::LineParseError { line_number: 12 })));
+}